@@ -0,0 +1,221 @@
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DiagnosticSeverity {
+   Error,
+   Warning,
+   Note,
+}
+
+impl DiagnosticSeverity {
+   pub fn as_str(&self) -> &str {
+      match self {
+         DiagnosticSeverity::Error => "error",
+         DiagnosticSeverity::Warning => "warning",
+         DiagnosticSeverity::Note => "note",
+      }
+   }
+   fn from_word(word: &str) -> DiagnosticSeverity {
+      match word.to_lowercase().as_str() {
+         "error" | "fatal" => DiagnosticSeverity::Error,
+         "warning" => DiagnosticSeverity::Warning,
+         _ => DiagnosticSeverity::Note,
+      }
+   }
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderDiagnostic {
+   pub file: Option<String>,
+   pub line: u32,
+   pub column: Option<u32>,
+   pub severity: DiagnosticSeverity,
+   pub msg: String,
+}
+
+impl fmt::Display for ShaderDiagnostic {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      let file = match &self.file {
+         None => "<source>",
+         Some(file) => file.as_str(),
+      };
+      match self.column {
+         None => write!(f, "{file}:{}: ", self.line),
+         Some(col) => write!(f, "{file}:{}:{col}: ", self.line),
+      }?;
+      write!(f, "{}: {}", self.severity.as_str(), self.msg)
+   }
+}
+
+// parses a driver info log into (source string number, diagnostic)
+pub(crate) fn parse_info_log(log: &str) -> Vec<(u32, ShaderDiagnostic)> {
+   log.lines().filter_map(parse_log_line).collect()
+}
+
+// same as parse_info_log, with each source string number mapped to its file
+pub(crate) fn map_info_log(log: &str, sources: &[Option<String>]) -> Vec<ShaderDiagnostic> {
+   let mut diagnostics = Vec::new();
+   for (source, mut diagnostic) in parse_info_log(log) {
+      diagnostic.file = match sources.get(source as usize) {
+         None => None,
+         Some(file) => file.clone(),
+      };
+      diagnostics.push(diagnostic);
+   }
+   diagnostics
+}
+
+fn parse_log_line(line: &str) -> Option<(u32, ShaderDiagnostic)> {
+   let line = line.trim();
+   if line.is_empty() {
+      return None;
+   }
+   // AMD, ANGLE, APPLE -> "ERROR: 0:12: 'x' : undeclared identifier"
+   for (prefix, severity) in [
+      ("ERROR:", DiagnosticSeverity::Error),
+      ("WARNING:", DiagnosticSeverity::Warning),
+      ("INFO:", DiagnosticSeverity::Note),
+   ] {
+      let rest = match line.strip_prefix(prefix) {
+         None => continue,
+         Some(rest) => rest.trim_start(),
+      };
+      let (source, rest) = take_num(rest)?;
+      let (line, rest) = take_num(rest.strip_prefix(':')?)?;
+      let msg = rest.strip_prefix(':')?.trim();
+      return Some((source, diagnostic(line, None, severity, msg)));
+   }
+
+   let (source, rest) = take_num(line)?;
+   match rest.chars().next()? {
+      // MESA -> "0:12(5): error: msg"
+      ':' => {
+         let (line, rest) = take_num(&rest[1..])?;
+         let (column, rest) = match rest.strip_prefix('(') {
+            None => (None, rest),
+            Some(rest) => {
+               let (col, rest) = take_num(rest)?;
+               (Some(col), rest.strip_prefix(')')?)
+            }
+         };
+         let rest = rest.strip_prefix(':')?.trim_start();
+         let (word, msg) = rest.split_once(':')?;
+         let severity = DiagnosticSeverity::from_word(word.trim());
+         Some((source, diagnostic(line, column, severity, msg.trim())))
+      }
+      // NVIDIA -> "0(12) : error C0000: msg"
+      '(' => {
+         let (line, rest) = take_num(&rest[1..])?;
+         let rest = rest.strip_prefix(')')?.trim_start();
+         let rest = rest.strip_prefix(':')?.trim_start();
+         let (head, msg) = rest.split_once(':')?;
+         let word = head.split_whitespace().next()?;
+         let severity = DiagnosticSeverity::from_word(word);
+         Some((source, diagnostic(line, None, severity, msg.trim())))
+      }
+      _ => None,
+   }
+}
+
+fn diagnostic(
+   line: u32,
+   column: Option<u32>,
+   severity: DiagnosticSeverity,
+   msg: &str,
+) -> ShaderDiagnostic {
+   ShaderDiagnostic {
+      file: None,
+      line,
+      column,
+      severity,
+      msg: msg.to_string(),
+   }
+}
+
+fn take_num(s: &str) -> Option<(u32, &str)> {
+   let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+   match s[..end].parse::<u32>() {
+      Err(_) => None,
+      Ok(n) => Some((n, &s[end..])),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn mesa_log() {
+      let parsed =
+         parse_info_log("0:12(5): error: `x' undeclared\n1:3(10): warning: unused variable");
+      assert_eq!(parsed.len(), 2);
+      let (source, diag) = &parsed[0];
+      assert_eq!(*source, 0);
+      assert_eq!(diag.line, 12);
+      assert_eq!(diag.column, Some(5));
+      assert_eq!(diag.severity, DiagnosticSeverity::Error);
+      assert_eq!(diag.msg, "`x' undeclared");
+      let (source, diag) = &parsed[1];
+      assert_eq!(*source, 1);
+      assert_eq!(diag.line, 3);
+      assert_eq!(diag.column, Some(10));
+      assert_eq!(diag.severity, DiagnosticSeverity::Warning);
+   }
+
+   #[test]
+   fn nvidia_log() {
+      let parsed = parse_info_log(
+         "2(40) : error C1008: undefined variable \"x\"\n0(7) : warning C7050: \"y\" might be used before being initialized",
+      );
+      assert_eq!(parsed.len(), 2);
+      let (source, diag) = &parsed[0];
+      assert_eq!(*source, 2);
+      assert_eq!(diag.line, 40);
+      assert_eq!(diag.column, None);
+      assert_eq!(diag.severity, DiagnosticSeverity::Error);
+      assert_eq!(diag.msg, "undefined variable \"x\"");
+      assert_eq!(parsed[1].1.severity, DiagnosticSeverity::Warning);
+      assert_eq!(parsed[1].1.line, 7);
+   }
+
+   #[test]
+   fn amd_log() {
+      let parsed = parse_info_log(
+         "ERROR: 1:12: 'x' : undeclared identifier\nWARNING: 0:4: 'y' : unused\nERROR: 2 compilation errors.  No code generated.",
+      );
+      assert_eq!(parsed.len(), 2);
+      let (source, diag) = &parsed[0];
+      assert_eq!(*source, 1);
+      assert_eq!(diag.line, 12);
+      assert_eq!(diag.severity, DiagnosticSeverity::Error);
+      assert_eq!(diag.msg, "'x' : undeclared identifier");
+      assert_eq!(parsed[1].1.severity, DiagnosticSeverity::Warning);
+   }
+
+   #[test]
+   fn unknown_lines_are_skipped() {
+      assert!(parse_info_log("").is_empty());
+      assert!(parse_info_log("link failed\n\nsomething went wrong").is_empty());
+   }
+
+   #[test]
+   fn sources_map_to_files() {
+      let sources = vec![
+         Some("main.frag".to_string()),
+         None,
+         Some("light.glsl".to_string()),
+      ];
+      let diags = map_info_log(
+         "0:1(1): error: a\n2:8(3): error: b\n1:2(1): error: c\n9:4(1): error: d",
+         &sources,
+      );
+      assert_eq!(diags.len(), 4);
+      assert_eq!(diags[0].file.as_deref(), Some("main.frag"));
+      assert_eq!(diags[1].file.as_deref(), Some("light.glsl"));
+      assert_eq!(diags[1].line, 8);
+      assert_eq!(diags[2].file, None);
+      assert_eq!(diags[3].file, None);
+      assert_eq!(diags[1].to_string(), "light.glsl:8:3: error: b");
+      assert_eq!(diags[2].to_string(), "<source>:2:1: error: c");
+   }
+}
@@ -1,8 +1,18 @@
+use crate::asset::util;
 use crate::renderer::ImgFormat;
-use crate::{GLueError, GLueErrorKind, ImgFilter, ImgWrap, Size2D, Texture2D};
+use crate::renderer::glraw::Ctx;
+use crate::renderer::{
+   GlObject, ObjectKind, PendingImage, PendingRead, create_read_buffer, pack_barrier,
+};
+use crate::{GLueError, GLueErrorKind, ImgFilter, ImgWrap, Size2D, Texture2D, file};
 use gl::types::{GLenum, GLint, GLsizei};
-use image::{ColorType, EncodableLayout, GenericImageView, Rgba, RgbaImage};
+use image::{
+   ColorType, DynamicImage, EncodableLayout, GenericImageView, ImageBuffer, ImageFormat, Rgba,
+   RgbaImage,
+};
 use std::ffi::c_void;
+use std::io::Cursor;
+use std::ptr;
 
 #[derive(Debug)]
 pub struct Image {
@@ -19,10 +29,11 @@ impl Image {
       let ((w, h), rgba8) = match image::open(path) {
          Ok(i) => (i.dimensions(), i.into_rgba8()),
          Err(e) => {
-            return Err(GLueError::from(
-               GLueErrorKind::WierdFile,
-               &format!("wierd file {path} {e}"),
-            ));
+            return Err(
+               GLueError::from(GLueErrorKind::WierdFile, &format!("wierd file {path} {e}"))
+                  .with_path(path)
+                  .with_source(e),
+            );
          }
       };
       let color = ColorType::Rgba8;
@@ -61,10 +72,13 @@ impl Image {
          ColorType::Rgb32F => ImgFormat::RGB(32),
          ColorType::Rgba32F => ImgFormat::RGBA(32),
          _ => {
-            return Err(GLueError::from(
-               GLueErrorKind::WierdFile,
-               &format!("invalid color type {path}"),
-            ));
+            return Err(
+               GLueError::from(
+                  GLueErrorKind::WierdFile,
+                  &format!("invalid color type {path}"),
+               )
+               .with_path(path),
+            );
          }
       };
       let filter = ImgFilter::Closest;
@@ -78,9 +92,109 @@ impl Image {
       })
    }
 
+   pub(crate) fn from_bytes(bytes: Vec<u8>, size: Size2D, fmt: ImgFormat) -> Image {
+      Image {
+         bytes,
+         size,
+         fmt,
+         filter: ImgFilter::Closest,
+         wrap: ImgWrap::Clip,
+      }
+   }
+
+   pub fn save(&self, path: &str) -> Result<(), GLueError> {
+      let wierd_err = Err(
+         GLueError::from(GLueErrorKind::WierdFile, &format!("wierd file {path}")).with_path(path),
+      );
+      let name = match file::name(path) {
+         None => return wierd_err,
+         Some(n) => n,
+      };
+      match file::ex(path) {
+         None => return wierd_err,
+         Some(ex) => match ex.eq_ignore_ascii_case(util::ex::PNG) {
+            false => return wierd_err,
+            true => ex,
+         },
+      };
+
+      let dyn_img = match self.as_dynamic() {
+         None => {
+            return Err(GLueError::from(
+               GLueErrorKind::CouldNotMake,
+               &format!(
+                  "image bytes do not match {}x{} {:?}",
+                  self.size.w, self.size.h, self.fmt
+               ),
+            ));
+         }
+         Some(i) => i,
+      };
+      let mut png = Cursor::new(Vec::new());
+      match dyn_img.write_to(&mut png, ImageFormat::Png) {
+         Err(e) => {
+            return Err(
+               GLueError::from(
+                  GLueErrorKind::CouldNotMake,
+                  &format!("could not encode {path} {e}"),
+               )
+               .with_path(path)
+               .with_source(e),
+            );
+         }
+         Ok(_) => {}
+      }
+      let dir = file::dir(path);
+      file::write_bytes_to_disk(&dir, &format!("{name}.{}", util::ex::PNG), png.get_ref())
+   }
+
+   fn as_dynamic(&self) -> Option<DynamicImage> {
+      let (w, h) = (self.size.w, self.size.h);
+      let channels = self.fmt.channels();
+      match self.fmt.bit_depth() {
+         8 => {
+            let raw = self.bytes.clone();
+            Some(match channels {
+               1 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(w, h, raw)?),
+               2 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(w, h, raw)?),
+               3 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(w, h, raw)?),
+               _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(w, h, raw)?),
+            })
+         }
+         bd @ (16 | 32) => {
+            let raw: Vec<u16> = match bd {
+               16 => self
+                  .bytes
+                  .chunks_exact(2)
+                  .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+                  .collect(),
+               _ => self
+                  .bytes
+                  .chunks_exact(4)
+                  .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                  .map(|f| (f.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16)
+                  .collect(),
+            };
+            Some(match channels {
+               1 => DynamicImage::ImageLuma16(ImageBuffer::from_raw(w, h, raw)?),
+               2 => DynamicImage::ImageLumaA16(ImageBuffer::from_raw(w, h, raw)?),
+               3 => DynamicImage::ImageRgb16(ImageBuffer::from_raw(w, h, raw)?),
+               _ => DynamicImage::ImageRgba16(ImageBuffer::from_raw(w, h, raw)?),
+            })
+         }
+         _ => None,
+      }
+   }
+
    pub fn size(&self) -> Size2D {
       self.size
    }
+   pub fn fmt(&self) -> ImgFormat {
+      self.fmt
+   }
+   pub fn bytes(&self) -> &[u8] {
+      &self.bytes
+   }
    pub fn set_wrap(&mut self, wrap: ImgWrap) {
       self.wrap = wrap
    }
@@ -95,14 +209,7 @@ impl Image {
    }
 
    pub fn pixel_count(&self) -> usize {
-      let (channels, bits) = match self.fmt {
-         ImgFormat::R(b) => (1, b),
-         ImgFormat::RG(b) => (2, b),
-         ImgFormat::RGB(b) => (3, b),
-         ImgFormat::RGBA(b) => (4, b),
-      };
-
-      let bytes_per_pixel = (channels as usize) * (bits as usize / 8);
+      let bytes_per_pixel = self.fmt.bytes_per_pixel();
       if bytes_per_pixel == 0 {
          return 0;
       }
@@ -113,11 +220,12 @@ impl Image {
    pub fn ship(self) -> Texture2D {
       let id = create_texture2d(&self);
       Texture2D {
-         id,
+         obj: GlObject::shared(ObjectKind::Texture, id),
          size: self.size,
          fmt: self.fmt,
          filter: self.filter,
          wrap: self.wrap,
+         label: None,
       }
    }
 }
@@ -138,24 +246,58 @@ pub(crate) fn create_texture2d(img: &Image) -> u32 {
       gl::TexParameteri(TEX, gl::TEXTURE_WRAP_S, wrap);
       gl::TexParameteri(TEX, gl::TEXTURE_WRAP_T, wrap);
 
-      let (base, size) = match_tex_fmt(&img.fmt);
-      let (width, height) = (img.size.w as GLsizei, img.size.h as GLsizei);
+      fill_texture2d(
+         img.size,
+         &img.fmt,
+         &img.bytes[0] as *const u8 as *const c_void,
+      );
+      gl::GenerateMipmap(TEX);
+      unbind_texture2d()
+   }
+   id
+}
+
+pub(crate) fn create_empty_texture2d(
+   size: Size2D,
+   fmt: &ImgFormat,
+   filter: &ImgFilter,
+   wrap: &ImgWrap,
+) -> u32 {
+   let mut id = 0;
+   unsafe {
+      gl::GenTextures(1, &mut id);
+      bind_texture2d_sampler_at(id, 0);
 
+      let wrap = match_tex_wrap(wrap);
+      let (_, mag_fil) = match_tex_filter(filter);
+
+      gl::TexParameteri(TEX, gl::TEXTURE_MIN_FILTER, mag_fil);
+      gl::TexParameteri(TEX, gl::TEXTURE_MAG_FILTER, mag_fil);
+      gl::TexParameteri(TEX, gl::TEXTURE_WRAP_S, wrap);
+      gl::TexParameteri(TEX, gl::TEXTURE_WRAP_T, wrap);
+
+      fill_texture2d(size, fmt, ptr::null());
+      unbind_texture2d()
+   }
+   id
+}
+
+fn fill_texture2d(size: Size2D, fmt: &ImgFormat, data: *const c_void) {
+   let (base, sized) = match_tex_fmt(fmt);
+   let typ = match_tex_type(fmt);
+   unsafe {
       gl::TexImage2D(
          TEX,
          0,
-         size as GLint,
-         width,
-         height,
+         sized as GLint,
+         size.w as GLsizei,
+         size.h as GLsizei,
          0,
          base,
-         gl::UNSIGNED_BYTE,
-         &img.bytes[0] as *const u8 as *const c_void,
+         typ,
+         data,
       );
-      gl::GenerateMipmap(TEX);
-      unbind_texture2d()
    }
-   id
 }
 
 pub(crate) fn bind_texture2d_sampler_at(tex_id: u32, slot: u32) {
@@ -182,12 +324,136 @@ pub(crate) fn delete_texture2d(id: u32) {
    }
 }
 
-fn match_tex_fmt(tf: &ImgFormat) -> (GLenum, GLenum) {
+// only depths that map onto a gl pixel type can be read back
+// es only guarantees RGBA/UNSIGNED_BYTE reads and cannot read depth at all
+pub(crate) fn check_read_format(fmt: &ImgFormat) -> Result<(), GLueError> {
+   if Ctx::current().is_es() {
+      return match fmt {
+         ImgFormat::RGBA(8) => Ok(()),
+         _ => Err(GLueError::from(
+            GLueErrorKind::Unsupported,
+            &format!("cannot read back {fmt:?} on opengl es, only RGBA(8) is readable"),
+         )),
+      };
+   }
+   let readable = match fmt {
+      ImgFormat::Depth(bd) => matches!(bd, 16 | 24 | 32),
+      ImgFormat::DepthStencil(bd) => matches!(bd, 24 | 32),
+      _ => matches!(fmt.bit_depth(), 8 | 16 | 32),
+   };
+   match readable {
+      true => Ok(()),
+      false => Err(GLueError::from(
+         GLueErrorKind::Unsupported,
+         &format!("cannot read back {fmt:?}, bit depth must be 8, 16 or 32"),
+      )),
+   }
+}
+
+// bytes per pixel as written by ReadPixels for the type match_tex_type picks
+pub(crate) fn read_pixel_size(fmt: &ImgFormat) -> usize {
+   let channels = fmt.channels() as usize;
+   match match_tex_type(fmt) {
+      gl::FLOAT_32_UNSIGNED_INT_24_8_REV => 8,
+      gl::UNSIGNED_INT_24_8 => 4,
+      gl::FLOAT => channels * 4,
+      gl::UNSIGNED_SHORT => channels * 2,
+      _ => channels,
+   }
+}
+
+pub(crate) fn read_framebuffer(size: Size2D, fmt: &ImgFormat) -> Result<Vec<u8>, GLueError> {
+   match check_read_format(fmt) {
+      Err(e) => return Err(e),
+      Ok(()) => {}
+   }
+   let (base, _) = match_tex_fmt(fmt);
+   let typ = match_tex_type(fmt);
+   let row_size = size.w as usize * read_pixel_size(fmt);
+   let mut bytes = vec![0u8; row_size * size.h as usize];
+   if bytes.is_empty() {
+      return Ok(bytes);
+   }
+   unsafe {
+      gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+      gl::ReadPixels(
+         0,
+         0,
+         size.w as GLsizei,
+         size.h as GLsizei,
+         base,
+         typ,
+         bytes.as_mut_ptr() as *mut c_void,
+      );
+   }
+   flip_rows(&mut bytes, row_size);
+   Ok(bytes)
+}
+
+pub(crate) fn read_framebuffer_async(
+   size: Size2D,
+   fmt: &ImgFormat,
+) -> Result<PendingImage, GLueError> {
+   match check_read_format(fmt) {
+      Err(e) => return Err(e),
+      Ok(()) => {}
+   }
+   let (base, _) = match_tex_fmt(fmt);
+   let typ = match_tex_type(fmt);
+   let len = size.w as usize * size.h as usize * read_pixel_size(fmt);
+   pack_barrier();
+   let id = create_read_buffer(gl::PIXEL_PACK_BUFFER, len);
+   unsafe {
+      gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+      if len > 0 {
+         gl::ReadPixels(
+            0,
+            0,
+            size.w as GLsizei,
+            size.h as GLsizei,
+            base,
+            typ,
+            ptr::null_mut(),
+         );
+      }
+      gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+   }
+   Ok(PendingImage {
+      read: PendingRead::from_buffer(id, len),
+      size,
+      fmt: *fmt,
+   })
+}
+
+pub(crate) fn flip_rows(bytes: &mut [u8], row_size: usize) {
+   let rows = bytes.len() / row_size;
+   for i in 0..rows / 2 {
+      let (top, bottom) = bytes.split_at_mut((rows - i - 1) * row_size);
+      top[i * row_size..(i + 1) * row_size].swap_with_slice(&mut bottom[..row_size]);
+   }
+}
+
+fn match_tex_type(tf: &ImgFormat) -> GLenum {
+   match tf {
+      ImgFormat::Depth(_) => gl::FLOAT,
+      ImgFormat::DepthStencil(32) => gl::FLOAT_32_UNSIGNED_INT_24_8_REV,
+      ImgFormat::DepthStencil(_) => gl::UNSIGNED_INT_24_8,
+      _ => match tf.bit_depth() {
+         16 => gl::UNSIGNED_SHORT,
+         32 => gl::FLOAT,
+         _ => gl::UNSIGNED_BYTE,
+      },
+   }
+}
+
+pub(crate) fn match_tex_fmt(tf: &ImgFormat) -> (GLenum, GLenum) {
    let (base, bd) = match tf {
       ImgFormat::R(bd) => (gl::RED, bd),
       ImgFormat::RG(bd) => (gl::RG, bd),
       ImgFormat::RGB(bd) => (gl::RGB, bd),
       ImgFormat::RGBA(bd) => (gl::RGBA, bd),
+      ImgFormat::Depth(bd) => (gl::DEPTH_COMPONENT, bd),
+      ImgFormat::DepthStencil(bd) => (gl::DEPTH_STENCIL, bd),
    };
    let sized = match (base, bd) {
       (gl::RED, 16) => gl::R16,
@@ -195,6 +461,17 @@ fn match_tex_fmt(tf: &ImgFormat) -> (GLenum, GLenum) {
       (gl::RGB, 16) => gl::RGB16,
       (gl::RGBA, 16) => gl::RGBA16,
 
+      (gl::RED, 32) => gl::R32F,
+      (gl::RG, 32) => gl::RG32F,
+      (gl::RGB, 32) => gl::RGB32F,
+      (gl::RGBA, 32) => gl::RGBA32F,
+
+      (gl::DEPTH_COMPONENT, 16) => gl::DEPTH_COMPONENT16,
+      (gl::DEPTH_COMPONENT, 32) => gl::DEPTH_COMPONENT32F,
+      (gl::DEPTH_COMPONENT, _) => gl::DEPTH_COMPONENT24,
+      (gl::DEPTH_STENCIL, 32) => gl::DEPTH32F_STENCIL8,
+      (gl::DEPTH_STENCIL, _) => gl::DEPTH24_STENCIL8,
+
       (gl::RED, _) => gl::R8,
       (gl::RG, _) => gl::RG8,
       (gl::RGB, _) => gl::RGB8,
@@ -215,7 +492,61 @@ fn match_tex_wrap(tf: &ImgWrap) -> GLint {
    let wrap = match tf {
       ImgWrap::Repeat => gl::REPEAT,
       ImgWrap::Extend => gl::CLAMP_TO_EDGE,
-      ImgWrap::Clip => gl::CLAMP_TO_BORDER,
+      ImgWrap::Clip => match Ctx::current() {
+         ctx if ctx.is_es() && !ctx.at_least(3, 2) => gl::CLAMP_TO_EDGE,
+         _ => gl::CLAMP_TO_BORDER,
+      },
    };
    wrap as GLint
 }
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn flip_even_rows() {
+      let mut bytes = vec![1, 1, 2, 2, 3, 3, 4, 4];
+      flip_rows(&mut bytes, 2);
+      assert_eq!(bytes, [4, 4, 3, 3, 2, 2, 1, 1]);
+   }
+
+   #[test]
+   fn flip_odd_rows() {
+      let mut bytes = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
+      flip_rows(&mut bytes, 3);
+      assert_eq!(bytes, [7, 8, 9, 4, 5, 6, 1, 2, 3]);
+   }
+
+   #[test]
+   fn flip_single_row() {
+      let mut bytes = vec![1, 2, 3, 4];
+      flip_rows(&mut bytes, 4);
+      assert_eq!(bytes, [1, 2, 3, 4]);
+      let mut empty: Vec<u8> = Vec::new();
+      flip_rows(&mut empty, 4);
+      assert!(empty.is_empty());
+   }
+
+   #[test]
+   fn read_formats() {
+      assert!(check_read_format(&ImgFormat::RGBA(8)).is_ok());
+      assert!(check_read_format(&ImgFormat::RGB(16)).is_ok());
+      assert!(check_read_format(&ImgFormat::R(32)).is_ok());
+      assert!(check_read_format(&ImgFormat::Depth(24)).is_ok());
+      assert!(check_read_format(&ImgFormat::DepthStencil(32)).is_ok());
+      assert!(check_read_format(&ImgFormat::RGBA(4)).is_err());
+      assert!(check_read_format(&ImgFormat::Depth(8)).is_err());
+      assert!(check_read_format(&ImgFormat::DepthStencil(16)).is_err());
+   }
+
+   #[test]
+   fn read_pixel_sizes() {
+      assert_eq!(read_pixel_size(&ImgFormat::RGBA(8)), 4);
+      assert_eq!(read_pixel_size(&ImgFormat::RGB(16)), 6);
+      assert_eq!(read_pixel_size(&ImgFormat::RG(32)), 8);
+      assert_eq!(read_pixel_size(&ImgFormat::Depth(24)), 4);
+      assert_eq!(read_pixel_size(&ImgFormat::DepthStencil(24)), 4);
+      assert_eq!(read_pixel_size(&ImgFormat::DepthStencil(32)), 8);
+   }
+}
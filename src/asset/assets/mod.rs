@@ -1,9 +1,15 @@
 mod attr;
+mod diag;
 mod img;
 mod msh;
+mod preproc;
 mod shdr;
+mod spv;
 
 pub use attr::*;
+pub use diag::*;
 pub use img::*;
 pub use msh::*;
+pub(crate) use preproc::*;
 pub use shdr::*;
+pub(crate) use spv::*;
@@ -1,8 +1,10 @@
 use crate::asset::util;
+use crate::renderer::glraw::Ctx;
 use crate::*;
 use cgmath::Vector2;
 use std::collections::HashMap;
 use std::ops::Deref;
+use std::rc::Rc;
 
 enum OBJ {
    Parsed {
@@ -150,7 +152,7 @@ impl Mesh3DFile {
    }
 
    fn from_path(path: &str) -> Result<Mesh3DFile, GLueError> {
-      let wierd = Err(GLueError::from(GLueErrorKind::WierdFile, path));
+      let wierd = Err(GLueError::from(GLueErrorKind::WierdFile, path).with_path(path));
       match file::name(path) {
          None => return wierd,
          Some(n) => n,
@@ -170,10 +172,13 @@ impl Mesh3DFile {
          };
          let msh = match OBJ::parse(&obj_src) {
             OBJ::NonTriangle(line) => {
-               return Err(GLueError::from(
-                  GLueErrorKind::NotTriangle,
-                  &format!("{path} -> line {line}"),
-               ));
+               return Err(
+                  GLueError::from(
+                     GLueErrorKind::NotTriangle,
+                     &format!("{path} -> line {line}"),
+                  )
+                  .with_path(path),
+               );
             }
             OBJ::Parsed {
                pos_attr,
@@ -192,10 +197,10 @@ impl Mesh3DFile {
          };
          Ok(msh)
       } else {
-         Err(GLueError::from(
-            GLueErrorKind::Missing,
-            &format!("file missing {path}"),
-         ))
+         Err(
+            GLueError::from(GLueErrorKind::Missing, &format!("file missing {path}"))
+               .with_path(path),
+         )
       }
    }
 
@@ -220,14 +225,21 @@ impl Mesh3DFile {
       !self.cus_attrs.is_empty()
    }
 
-   pub fn ship(self) -> Mesh3D {
+   pub fn ship(self) -> Result<Mesh3D, GLueError> {
+      match unsupported_attrs(&self.cus_attrs) {
+         Some(e) => return Err(e),
+         None => {}
+      }
       let handle = create_mesh3d_handle(&self);
-      Mesh3D {
-         handle,
+      Ok(Mesh3D {
+         handle: Rc::new(handle),
+         draw_mode: DrawMode::default(),
          visibility: true,
          shader: None,
+         label: None,
+         state: None,
          transform: Transform3D::default(),
-      }
+      })
    }
 }
 
@@ -380,15 +392,43 @@ impl Mesh2DFile {
       !self.cus_attrs.is_empty()
    }
 
-   pub fn ship(self) -> Mesh2D {
+   pub fn ship(self) -> Result<Mesh2D, GLueError> {
+      match unsupported_attrs(&self.cus_attrs) {
+         Some(e) => return Err(e),
+         None => {}
+      }
       let handle = create_mesh2d_handle(&self);
-      Mesh2D {
-         handle,
+      Ok(Mesh2D {
+         handle: Rc::new(handle),
+         draw_mode: DrawMode::default(),
          visibility: true,
          shader: None,
+         label: None,
+         state: None,
          transform: Transform2D::default(),
+      })
+   }
+}
+
+fn unsupported_attrs(cus_attrs: &[CustomATTR]) -> Option<GLueError> {
+   if !Ctx::current().is_es() {
+      return None;
+   }
+   for cus_attr in cus_attrs.iter() {
+      match cus_attr.info.typ {
+         ATTRType::F64 => {
+            return Some(GLueError::from(
+               GLueErrorKind::Unsupported,
+               &format!(
+                  "{} f64 attributes are unsupported on opengl es",
+                  cus_attr.info.name.as_string()
+               ),
+            ));
+         }
+         _ => {}
       }
    }
+   None
 }
 
 fn create_mesh3d_handle(mesh: &Mesh3DFile) -> MeshHandle {
@@ -527,16 +567,14 @@ fn create_mesh3d_handle(mesh: &Mesh3DFile) -> MeshHandle {
       fill_index_buffer(ind_id, &index_buffer);
       unbind_index_buffer();
    }
-   let draw_mode = DrawMode::default();
    MeshHandle {
       layouts,
-      draw_mode,
       has_indices,
       vert_count,
       ind_count,
-      vao_id,
-      buf_id,
-      ind_id,
+      vao: GlObject::new(ObjectKind::VertexArray, vao_id),
+      buf: GlObject::new(ObjectKind::Buffer, buf_id),
+      ind: GlObject::new(ObjectKind::Buffer, ind_id),
    }
 }
 fn create_mesh2d_handle(mesh: &Mesh2DFile) -> MeshHandle {
@@ -661,16 +699,14 @@ fn create_mesh2d_handle(mesh: &Mesh2DFile) -> MeshHandle {
       fill_index_buffer(ind_id, &index_buffer);
       unbind_index_buffer();
    }
-   let draw_mode = DrawMode::default();
    MeshHandle {
       layouts,
-      draw_mode,
       has_indices,
       vert_count,
       ind_count,
-      vao_id,
-      buf_id,
-      ind_id,
+      vao: GlObject::new(ObjectKind::VertexArray, vao_id),
+      buf: GlObject::new(ObjectKind::Buffer, buf_id),
+      ind: GlObject::new(ObjectKind::Buffer, ind_id),
    }
 }
 
@@ -0,0 +1,350 @@
+use crate::asset::file;
+use crate::{GLueError, GLueErrorKind};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+   static VIRTUAL_INCLUDES: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
+pub(crate) fn add_include(name: &str, src: &str) {
+   VIRTUAL_INCLUDES.with(|v| v.borrow_mut().insert(name.to_string(), src.to_string()));
+}
+pub(crate) fn remove_include(name: &str) -> bool {
+   VIRTUAL_INCLUDES.with(|v| v.borrow_mut().remove(name).is_some())
+}
+pub(crate) fn clear_includes() {
+   VIRTUAL_INCLUDES.with(|v| v.borrow_mut().clear())
+}
+fn virtual_include(name: &str) -> Option<String> {
+   VIRTUAL_INCLUDES.with(|v| v.borrow().get(name).cloned())
+}
+
+// text keeps its indentation so driver columns match the file, directives are matched trimmed
+#[derive(Clone, Debug)]
+pub(crate) struct SrcLine {
+   pub(crate) text: String,
+   pub(crate) file: u32,
+   pub(crate) line: u32,
+}
+
+impl SrcLine {
+   pub(crate) fn split(src: &str, file: u32) -> Vec<SrcLine> {
+      src.lines()
+         .enumerate()
+         .map(|(i, text)| SrcLine {
+            text: text.to_string(),
+            file,
+            line: i as u32 + 1,
+         })
+         .collect()
+   }
+}
+
+// index into files is the source string number used in #line
+pub(crate) struct Preprocessor<'a> {
+   files: &'a mut Vec<Option<String>>,
+   stack: Vec<String>,
+   once: Vec<String>,
+}
+
+impl<'a> Preprocessor<'a> {
+   pub(crate) fn new(files: &'a mut Vec<Option<String>>) -> Preprocessor<'a> {
+      Preprocessor {
+         files,
+         stack: Vec::new(),
+         once: Vec::new(),
+      }
+   }
+
+   pub(crate) fn expand(&mut self, lines: Vec<SrcLine>) -> Result<Vec<SrcLine>, GLueError> {
+      let mut out = Vec::with_capacity(lines.len());
+      self.stack.clear();
+      match lines.first() {
+         None => {}
+         Some(first) => match self.file_name(first.file) {
+            None => {}
+            Some(root) => self.stack.push(root),
+         },
+      }
+      match self.expand_into(lines, &mut out) {
+         Err(e) => Err(e),
+         Ok(()) => Ok(out),
+      }
+   }
+
+   fn expand_into(&mut self, lines: Vec<SrcLine>, out: &mut Vec<SrcLine>) -> Result<(), GLueError> {
+      for line in lines {
+         if is_pragma_once(line.text.trim()) {
+            match self.file_name(line.file) {
+               None => {}
+               Some(name) => self.once.push(name),
+            }
+            continue;
+         }
+         let name = match include_target(line.text.trim()) {
+            None => {
+               out.push(line);
+               continue;
+            }
+            Some(name) => name.to_string(),
+         };
+         let includer = self.file_name(line.file);
+         let (resolved, src) = match resolve(&name, includer.as_deref()) {
+            Err(e) => return Err(e),
+            Ok(None) => {
+               let from = includer.as_deref().unwrap_or("<source>");
+               let err = GLueError::from(
+                  GLueErrorKind::MissingInclude,
+                  &format!("missing include {name} in {from}:{}", line.line),
+               );
+               return Err(err.with_path(&include_path(&name, includer.as_deref())));
+            }
+            Ok(Some(found)) => found,
+         };
+         if self.once.contains(&resolved) {
+            continue;
+         }
+         if self.stack.contains(&resolved) {
+            let mut chain = self.stack.clone();
+            chain.push(resolved);
+            return Err(GLueError::from(
+               GLueErrorKind::IncludeCycle,
+               &format!("include cycle {}", chain.join(" -> ")),
+            ));
+         }
+
+         let file = self.file_index(&resolved);
+         self.stack.push(resolved);
+         match self.expand_into(SrcLine::split(&src, file), out) {
+            Err(e) => return Err(e),
+            Ok(()) => {}
+         }
+         self.stack.pop();
+      }
+      Ok(())
+   }
+
+   fn file_name(&self, file: u32) -> Option<String> {
+      match self.files.get(file as usize) {
+         None => None,
+         Some(name) => name.clone(),
+      }
+   }
+
+   fn file_index(&mut self, name: &str) -> u32 {
+      match self.files.iter().position(|f| f.as_deref() == Some(name)) {
+         Some(i) => i as u32,
+         None => {
+            self.files.push(Some(name.to_string()));
+            self.files.len() as u32 - 1
+         }
+      }
+   }
+}
+
+fn is_pragma_once(text: &str) -> bool {
+   match text.strip_prefix('#') {
+      None => false,
+      Some(rest) => {
+         let mut words = rest.split_whitespace();
+         words.next() == Some("pragma") && words.next() == Some("once") && words.next().is_none()
+      }
+   }
+}
+
+fn include_target(text: &str) -> Option<&str> {
+   let rest = text.strip_prefix('#')?.trim_start();
+   let rest = rest.strip_prefix("include")?.trim();
+   match rest.chars().next()? {
+      '"' => rest.strip_prefix('"')?.strip_suffix('"'),
+      '<' => rest.strip_prefix('<')?.strip_suffix('>'),
+      _ => None,
+   }
+}
+
+fn include_path(name: &str, includer: Option<&str>) -> String {
+   match includer {
+      None => name.to_string(),
+      Some(path) => format!("{}{name}", file::dir(path)),
+   }
+}
+
+// virtual includes win over disk, both are looked up relative to the includer first,
+// Ok(None) means nothing was found, a file that exists but cannot be read is an error
+fn resolve(name: &str, includer: Option<&str>) -> Result<Option<(String, String)>, GLueError> {
+   let relative = include_path(name, includer);
+   for candidate in [relative.as_str(), name] {
+      match virtual_include(candidate) {
+         None => {}
+         Some(src) => return Ok(Some((candidate.to_string(), src))),
+      }
+   }
+   if !file::exists_on_disk(&relative) {
+      return Ok(None);
+   }
+   match file::read_as_string(&relative) {
+      Err(e) => Err(e),
+      Ok(src) => Ok(Some((relative, src))),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use std::fs;
+
+   fn expand(
+      src: &str,
+      root: Option<&str>,
+   ) -> (Result<Vec<SrcLine>, GLueError>, Vec<Option<String>>) {
+      let mut files = vec![root.map(|r| r.to_string())];
+      let res = Preprocessor::new(&mut files).expand(SrcLine::split(src, 0));
+      (res, files)
+   }
+
+   fn texts(lines: &[SrcLine]) -> Vec<&str> {
+      lines.iter().map(|l| l.text.as_str()).collect()
+   }
+
+   #[test]
+   fn include_targets() {
+      assert_eq!(
+         include_target("#include \"light.glsl\""),
+         Some("light.glsl")
+      );
+      assert_eq!(
+         include_target("# include <lib/noise.glsl>"),
+         Some("lib/noise.glsl")
+      );
+      assert_eq!(include_target("#include light.glsl"), None);
+      assert_eq!(include_target("#include \"light.glsl"), None);
+      assert_eq!(include_target("// #include \"x\""), None);
+   }
+
+   #[test]
+   fn virtual_include() {
+      clear_includes();
+      add_include("common.glsl", "float a;\nfloat b;");
+      let (res, files) = expand(
+         "#version 330\n#include \"common.glsl\"\nvoid main() {}",
+         None,
+      );
+      let lines = res.unwrap();
+      assert_eq!(
+         texts(&lines),
+         ["#version 330", "float a;", "float b;", "void main() {}"]
+      );
+      assert_eq!(files, [None, Some("common.glsl".to_string())]);
+      assert_eq!((lines[1].file, lines[1].line), (1, 1));
+      assert_eq!((lines[2].file, lines[2].line), (1, 2));
+      assert_eq!((lines[3].file, lines[3].line), (0, 3));
+      assert!(remove_include("common.glsl"));
+      assert!(!remove_include("common.glsl"));
+   }
+
+   #[test]
+   fn indentation_is_kept() {
+      clear_includes();
+      add_include("indent.glsl", "   float a;\n\tfloat b;");
+      let (res, _) = expand(
+         "void main() {\n   #include \"indent.glsl\"  \n   gl_Position = vec4(0.0);\n}",
+         None,
+      );
+      assert_eq!(
+         texts(&res.unwrap()),
+         [
+            "void main() {",
+            "   float a;",
+            "\tfloat b;",
+            "   gl_Position = vec4(0.0);",
+            "}"
+         ]
+      );
+      clear_includes();
+   }
+
+   #[test]
+   fn virtual_include_relative_to_includer() {
+      clear_includes();
+      add_include("shaders/lib/a.glsl", "#include \"b.glsl\"\nfloat a;");
+      add_include("shaders/lib/b.glsl", "float b;");
+      add_include("b.glsl", "float wrong;");
+      let (res, files) = expand("#include \"lib/a.glsl\"", Some("shaders/main.frag"));
+      assert_eq!(texts(&res.unwrap()), ["float b;", "float a;"]);
+      assert_eq!(files[1].as_deref(), Some("shaders/lib/a.glsl"));
+      assert_eq!(files[2].as_deref(), Some("shaders/lib/b.glsl"));
+      clear_includes();
+   }
+
+   #[test]
+   fn relative_include_on_disk() {
+      clear_includes();
+      let dir = std::env::temp_dir().join(format!("glue-preproc-{}", std::process::id()));
+      fs::create_dir_all(dir.join("lib")).unwrap();
+      fs::write(
+         dir.join("lib/light.glsl"),
+         "#include \"util.glsl\"\nvec3 light;",
+      )
+      .unwrap();
+      fs::write(dir.join("lib/util.glsl"), "float util;").unwrap();
+      let root = format!("{}/main.frag", dir.to_string_lossy());
+
+      let (res, files) = expand("#include \"lib/light.glsl\"\nvoid main() {}", Some(&root));
+      fs::remove_dir_all(&dir).unwrap();
+      let lines = res.unwrap();
+      assert_eq!(
+         texts(&lines),
+         ["float util;", "vec3 light;", "void main() {}"]
+      );
+      assert_eq!(files.len(), 3);
+      assert!(files[1].as_deref().unwrap().ends_with("lib/light.glsl"));
+      assert!(files[2].as_deref().unwrap().ends_with("lib/util.glsl"));
+      assert_eq!((lines[0].file, lines[1].file, lines[2].file), (2, 1, 0));
+   }
+
+   #[test]
+   fn missing_include() {
+      clear_includes();
+      let (res, _) = expand(
+         "void a();\n#include \"nope.glsl\"",
+         Some("shaders/main.frag"),
+      );
+      let err = res.err().unwrap();
+      assert_eq!(err.kind(), GLueErrorKind::MissingInclude);
+      assert_eq!(err.path(), Some("shaders/nope.glsl"));
+      assert!(err.msg().contains("shaders/main.frag:2"));
+   }
+
+   #[test]
+   fn pragma_once() {
+      clear_includes();
+      add_include("once.glsl", "#pragma once\nfloat once;");
+      add_include("twice.glsl", "float twice;");
+      let src = "#include \"once.glsl\"\n#include \"twice.glsl\"\n#include \"once.glsl\"\n#include \"twice.glsl\"";
+      let (res, _) = expand(src, None);
+      assert_eq!(
+         texts(&res.unwrap()),
+         ["float once;", "float twice;", "float twice;"]
+      );
+      assert!(is_pragma_once("#  pragma   once"));
+      assert!(!is_pragma_once("#pragma once twice"));
+      clear_includes();
+   }
+
+   #[test]
+   fn include_cycle() {
+      clear_includes();
+      add_include("a.glsl", "#include \"b.glsl\"");
+      add_include("b.glsl", "#include \"a.glsl\"");
+      let (res, _) = expand("#include \"a.glsl\"", None);
+      let err = res.err().unwrap();
+      assert_eq!(err.kind(), GLueErrorKind::IncludeCycle);
+      assert!(err.msg().contains("a.glsl -> b.glsl -> a.glsl"));
+
+      add_include("self.glsl", "#include \"self.glsl\"");
+      let (res, _) = expand("#include \"self.glsl\"", Some("self.glsl"));
+      assert_eq!(res.err().unwrap().kind(), GLueErrorKind::IncludeCycle);
+      clear_includes();
+   }
+}
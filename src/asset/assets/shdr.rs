@@ -1,74 +1,131 @@
+use crate::renderer::glraw::Ctx;
 use crate::*;
 use gl::types::{GLchar, GLenum, GLint, GLsizei, GLuint};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
 use std::ffi::CString;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+pub(crate) struct StageSrc {
+   pub(crate) typ: ShaderSrcType,
+   pub(crate) src: String,
+   pub(crate) spirv: Option<SpirvStage>,
+}
+
+impl StageSrc {
+   // every line that does not follow on from the previous one gets a #line so the
+   // driver reports the file and line it actually came from, only comments and blank
+   // lines may come before #version so nothing is emitted until it has been passed
+   fn from_lines(typ: ShaderSrcType, lines: &[SrcLine]) -> StageSrc {
+      let mut src = String::new();
+      let mut next = None;
+      let is_version = |l: &SrcLine| l.text.trim_start().starts_with("#version");
+      let mut past_version = !lines.iter().any(is_version);
+      for line in lines {
+         let is_version = is_version(line);
+         if past_version && next != Some((line.file, line.line)) {
+            src.push_str(&format!("#line {} {}\n", line.line, line.file));
+         }
+         src.push_str(&line.text);
+         src.push_str("\n");
+         match (past_version, is_version) {
+            (_, true) => {
+               past_version = true;
+               next = None;
+            }
+            (true, false) => next = Some((line.file, line.line + 1)),
+            (false, false) => {}
+         }
+      }
+      StageSrc {
+         typ,
+         src,
+         spirv: None,
+      }
+   }
+   fn from_spirv(typ: ShaderSrcType, spirv: SpirvStage) -> StageSrc {
+      StageSrc {
+         typ,
+         src: String::new(),
+         spirv: Some(spirv),
+      }
+   }
+}
 
 enum GLSL {
-   ParsedCompute(String),
-   ParsedPipeline { v_src: String, f_src: String },
-   FailedPipeline { v_missing: bool, f_missing: bool },
+   Parsed(Vec<(ShaderSrcType, Vec<SrcLine>)>),
+   Failed(Vec<ShaderSrcType>),
 }
 impl GLSL {
    fn parse(src: &str, typ: ShaderType) -> GLSL {
-      let mut v_src = String::new();
-      let mut f_src = String::new();
-
+      let lines = SrcLine::split(src, 0);
       if typ.is_compute() {
-         return GLSL::ParsedCompute(src.to_string());
+         return GLSL::Parsed(vec![(ShaderSrcType::Compute, lines)]);
       }
 
-      let glsl_lines = src.lines();
+      let mut sections: Vec<(ShaderSrcType, Vec<SrcLine>, bool)> = ShaderSrcType::PIPELINE
+         .iter()
+         .map(|typ| (*typ, Vec::new(), false))
+         .collect();
+      let mut cur = 0;
 
-      let (mut v_found, mut f_found) = (false, false);
-      let mut cur_src = &mut v_src;
-
-      for line in glsl_lines {
-         let line = line.trim();
-         match line {
-            "//v" | "//V" | "//vert" | "//VERT" | "//vertex" | "//VERTEX" | "// v" | "// V"
-            | "// vert" | "// VERT" | "// vertex" | "// VERTEX" => {
-               cur_src = &mut v_src;
-               v_found = true;
-            }
-            "//f" | "//F" | "//frag" | "//FRAG" | "//fragment" | "//FRAGMENT" | "// f" | "// F"
-            | "// frag" | "// FRAG" | "// fragment" | "// FRAGMENT" => {
-               cur_src = &mut f_src;
-               f_found = true;
-            }
-            _ => {
-               cur_src.push_str(line);
-               cur_src.push_str("\n")
+      for line in lines {
+         match stage_marker(line.text.trim()) {
+            Some(typ) => {
+               cur = sections.iter().position(|(t, _, _)| *t == typ).unwrap_or(0);
+               sections[cur].2 = true;
             }
+            None => sections[cur].1.push(line),
+         }
+      }
+
+      let found = |typ: ShaderSrcType, sections: &[(ShaderSrcType, Vec<SrcLine>, bool)]| {
+         sections
+            .iter()
+            .any(|(t, lines, found)| *t == typ && *found && !lines.is_empty())
+      };
+      let mut missing = Vec::new();
+      for typ in [ShaderSrcType::Vert, ShaderSrcType::Frag] {
+         if !found(typ, &sections) {
+            missing.push(typ)
          }
       }
-      let (mut v_missing, mut f_missing) = (false, false);
-      if v_src.is_empty() || !v_found {
-         v_missing = true
+      if found(ShaderSrcType::TessCtrl, &sections) && !found(ShaderSrcType::TessEval, &sections) {
+         missing.push(ShaderSrcType::TessEval)
       }
-      if f_src.is_empty() || !f_found {
-         f_missing = true
+      if !missing.is_empty() {
+         return GLSL::Failed(missing);
       }
 
-      match v_missing || f_missing {
-         true => GLSL::FailedPipeline {
-            v_missing,
-            f_missing,
-         },
-         false => GLSL::ParsedPipeline { v_src, f_src },
-      }
+      GLSL::Parsed(
+         sections
+            .into_iter()
+            .filter(|(_, lines, found)| *found && !lines.is_empty())
+            .map(|(typ, lines, _)| (typ, lines))
+            .collect(),
+      )
    }
+}
 
-   fn is_missing(&self) -> (bool, bool) {
-      match self {
-         GLSL::ParsedPipeline { .. } => (true, true),
-         GLSL::FailedPipeline {
-            v_missing,
-            f_missing,
-         } => (*v_missing, *f_missing),
-         GLSL::ParsedCompute(_) => (true, true),
-      }
+// markers match exactly so ordinary comments never start a stage, the new stages only use
+// their file extension as a marker
+fn stage_marker(line: &str) -> Option<ShaderSrcType> {
+   match line {
+      "//v" | "//V" | "//vert" | "//VERT" | "//vertex" | "//VERTEX" | "// v" | "// V"
+      | "// vert" | "// VERT" | "// vertex" | "// VERTEX" => Some(ShaderSrcType::Vert),
+      "//tesc" | "//TESC" | "// tesc" | "// TESC" => Some(ShaderSrcType::TessCtrl),
+      "//tese" | "//TESE" | "// tese" | "// TESE" => Some(ShaderSrcType::TessEval),
+      "//geom" | "//GEOM" | "// geom" | "// GEOM" => Some(ShaderSrcType::Geom),
+      "//f" | "//F" | "//frag" | "//FRAG" | "//fragment" | "//FRAGMENT" | "// f" | "// F"
+      | "// frag" | "// FRAG" | "// fragment" | "// FRAGMENT" => Some(ShaderSrcType::Frag),
+      _ => None,
    }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ShaderType {
    Pipeline,
    Compute,
@@ -83,17 +140,104 @@ impl ShaderType {
    }
 }
 
-pub enum ShaderFile {
-   Pipe { v_src: String, f_src: String },
-   Comp(String),
+pub struct ShaderFile {
+   pub(crate) path: Option<String>,
+   pub(crate) sources: Vec<Option<String>>,
+   pub(crate) stages: Vec<StageSrc>,
+   pub(crate) defines: Vec<(String, String)>,
+   pub(crate) is_compute: bool,
+   pub(crate) origin: Option<ShaderOrigin>,
+   pub(crate) hot_reload: bool,
+}
+
+// how a shader file was read from disk, so it can be read again
+#[derive(Clone, Debug)]
+pub(crate) enum ShaderOrigin {
+   Path(String, ShaderType),
+   StagePaths(Vec<String>),
+}
+
+// everything a hot reloading shader needs to rebuild itself, files are (path, last seen mtime),
+// shared by every clone of the shader
+#[derive(Debug)]
+pub(crate) struct HotReload {
+   origin: ShaderOrigin,
+   defines: Vec<(String, String)>,
+   watched: RefCell<Vec<(String, Option<SystemTime>)>>,
+}
+
+impl HotReload {
+   fn new(file: &ShaderFile, origin: ShaderOrigin) -> HotReload {
+      let hot_reload = HotReload {
+         origin,
+         defines: file.defines.clone(),
+         watched: RefCell::new(Vec::new()),
+      };
+      hot_reload.watch_sources(file);
+      hot_reload
+   }
+
+   fn watch_sources(&self, file: &ShaderFile) {
+      let watched = file
+         .sources
+         .iter()
+         .flatten()
+         .filter(|path| file::exists_on_disk(path))
+         .map(|path| (path.clone(), file::modified(path)))
+         .collect();
+      *self.watched.borrow_mut() = watched;
+   }
+   fn watch(&self, path: &str) {
+      let mut watched = self.watched.borrow_mut();
+      if !watched.iter().any(|(p, _)| p == path) {
+         watched.push((path.to_string(), file::modified(path)));
+      }
+   }
+
+   pub(crate) fn changed(&self) -> bool {
+      self
+         .watched
+         .borrow()
+         .iter()
+         .any(|(path, seen)| file::modified(path) != *seen)
+   }
+   pub(crate) fn mark_seen(&self) {
+      for (path, seen) in self.watched.borrow_mut().iter_mut() {
+         *seen = file::modified(path);
+      }
+   }
+
+   // the watch list follows the latest parse, a failed parse also watches the file it failed on
+   // (e.g. a missing include) so creating or fixing it triggers the next reload
+   pub(crate) fn rebuild(&self) -> Result<u32, GLueError> {
+      let file = match &self.origin {
+         ShaderOrigin::Path(path, typ) => ShaderFile::from_path(path, *typ),
+         ShaderOrigin::StagePaths(paths) => {
+            let paths: Vec<&str> = paths.iter().map(|p| p.as_str()).collect();
+            ShaderFile::from_stage_paths(&paths)
+         }
+      };
+      let file = match file {
+         Err(e) => {
+            self.mark_seen();
+            match e.path() {
+               None => {}
+               Some(path) => self.watch(path),
+            }
+            return Err(e);
+         }
+         Ok(file) => file,
+      };
+      self.watch_sources(&file);
+      link_program(&file.stages, &self.defines, &file.sources, false)
+   }
 }
 
 impl ShaderFile {
    pub fn from_path(path: &str, typ: ShaderType) -> Result<ShaderFile, GLueError> {
-      let wierd_err = Err(GLueError::from(
-         GLueErrorKind::WierdFile,
-         &format!("wierd file {path}"),
-      ));
+      let wierd_err = Err(
+         GLueError::from(GLueErrorKind::WierdFile, &format!("wierd file {path}")).with_path(path),
+      );
       match file::name(path) {
          None => return wierd_err,
          Some(n) => n,
@@ -102,128 +246,766 @@ impl ShaderFile {
       match file::ex(path) {
          None => return wierd_err,
          Some(ex) => match ex.to_lowercase().as_str() {
-            "glsl" | "comp" | "shader" | "vert" | "frag" => ex,
+            "glsl" | "comp" | "shader" | "vert" | "frag" | "geom" | "tesc" | "tese" | "spv" => ex,
             _ => return wierd_err,
          },
       };
 
-      if file::exists_on_disk(path) {
+      if file::exists_on_disk(path) && is_spirv_path(path) {
+         let bytes = match file::read_as_bytes(path) {
+            Err(e) => return Err(e),
+            Ok(b) => b,
+         };
+         // lit.vert.spv holds one stage, anything else holds every stage of typ as main
+         let entry_points = match spirv_stage(path) {
+            Some(stage) => vec![(stage, "main")],
+            None => match typ.is_compute() {
+               true => vec![(ShaderSrcType::Compute, "main")],
+               false => vec![(ShaderSrcType::Vert, "main"), (ShaderSrcType::Frag, "main")],
+            },
+         };
+         match ShaderFile::from_spirv(&bytes, &entry_points, &[]) {
+            Err(e) => Err(e.with_path(path)),
+            Ok(mut shader_file) => {
+               shader_file.path = Some(path.to_string());
+               shader_file.sources = vec![Some(path.to_string())];
+               shader_file.origin = Some(ShaderOrigin::Path(path.to_string(), typ));
+               Ok(shader_file)
+            }
+         }
+      } else if file::exists_on_disk(path) {
          let src = match file::read_as_string(path) {
             Err(e) => return Err(e),
             Ok(s) => s,
          };
-         ShaderFile::from_src(&src, typ)
+         match ShaderFile::parse(&src, typ, Some(path)) {
+            Err(e) => Err(e),
+            Ok(mut shader_file) => {
+               shader_file.origin = Some(ShaderOrigin::Path(path.to_string(), typ));
+               Ok(shader_file)
+            }
+         }
       } else {
-         Err(GLueError::from(
-            GLueErrorKind::Missing,
-            &format!("missing file {path}"),
-         ))
+         Err(
+            GLueError::from(GLueErrorKind::Missing, &format!("missing file {path}"))
+               .with_path(path),
+         )
+      }
+   }
+
+   // one file per stage, the stage comes from the extension
+   pub fn from_stage_paths(paths: &[&str]) -> Result<ShaderFile, GLueError> {
+      let mut sources = Vec::new();
+      let mut stages = Vec::new();
+      for path in paths {
+         let typ = match is_spirv_path(path) {
+            true => spirv_stage(path),
+            false => match file::ex(path) {
+               None => None,
+               Some(ex) => ShaderSrcType::from_ex(&ex),
+            },
+         };
+         let typ = match typ {
+            None => {
+               return Err(
+                  GLueError::from(
+                     GLueErrorKind::WierdFile,
+                     &format!("no shader stage for {path}"),
+                  )
+                  .with_path(path),
+               );
+            }
+            Some(typ) => typ,
+         };
+         if !file::exists_on_disk(path) {
+            return Err(
+               GLueError::from(GLueErrorKind::Missing, &format!("missing file {path}"))
+                  .with_path(path),
+            );
+         }
+         sources.push(Some(path.to_string()));
+         if is_spirv_path(path) {
+            let bytes = match file::read_as_bytes(path) {
+               Err(e) => return Err(e),
+               Ok(b) => b,
+            };
+            match check_spirv_module(&bytes) {
+               Err(e) => return Err(e.with_path(path)),
+               Ok(()) => {}
+            }
+            let spirv = SpirvStage {
+               module: Rc::new(bytes),
+               entry: "main".to_string(),
+               constants: Rc::new(Vec::new()),
+            };
+            stages.push(StageSrc::from_spirv(typ, spirv));
+            continue;
+         }
+         let src = match file::read_as_string(path) {
+            Err(e) => return Err(e),
+            Ok(s) => s,
+         };
+         let file = sources.len() as u32 - 1;
+         let lines = match Preprocessor::new(&mut sources).expand(SrcLine::split(&src, file)) {
+            Err(e) => return Err(e),
+            Ok(lines) => lines,
+         };
+         stages.push(StageSrc::from_lines(typ, &lines));
       }
+      let origin = ShaderOrigin::StagePaths(paths.iter().map(|p| p.to_string()).collect());
+      match ShaderFile::from_stages(None, sources, stages) {
+         Err(e) => Err(e),
+         Ok(mut shader_file) => {
+            shader_file.origin = Some(origin);
+            Ok(shader_file)
+         }
+      }
+   }
+
+   pub fn from_vert_frag_src(v_src: &str, f_src: &str) -> Result<ShaderFile, GLueError> {
+      ShaderFile::from_stage_srcs(&[(ShaderSrcType::Vert, v_src), (ShaderSrcType::Frag, f_src)])
    }
 
-   pub fn from_vert_frag_src(v_src: &str, f_src: &str) -> ShaderFile {
-      ShaderFile::Pipe {
-         v_src: v_src.to_string(),
-         f_src: f_src.to_string(),
+   pub fn from_stage_srcs(srcs: &[(ShaderSrcType, &str)]) -> Result<ShaderFile, GLueError> {
+      let mut sources = vec![None];
+      let mut stages = Vec::new();
+      for (typ, src) in srcs {
+         let lines = match Preprocessor::new(&mut sources).expand(SrcLine::split(src, 0)) {
+            Err(e) => return Err(e),
+            Ok(lines) => lines,
+         };
+         stages.push(StageSrc::from_lines(*typ, &lines));
       }
+      ShaderFile::from_stages(None, sources, stages)
    }
 
-   pub fn from_src(src: &str, typ: ShaderType) -> Result<ShaderFile, GLueError> {
-      let glsl = GLSL::parse(&src, typ);
-      match glsl {
-         GLSL::FailedPipeline {
-            v_missing,
-            f_missing,
-         } => {
-            let missing = match (v_missing, f_missing) {
-               (true, true) => "vert + frag",
-               (true, _) => "vert",
-               _ => "frag",
-            };
-            Err(GLueError::from(
+   fn from_stages(
+      path: Option<&str>,
+      sources: Vec<Option<String>>,
+      mut stages: Vec<StageSrc>,
+   ) -> Result<ShaderFile, GLueError> {
+      stages.sort_by_key(|s| s.typ.order());
+      match stages.windows(2).find(|pair| pair[0].typ == pair[1].typ) {
+         None => {}
+         Some(pair) => {
+            return Err(GLueError::from(
                GLueErrorKind::MissingSrc,
-               &format!("missing {missing}"),
-            ))
+               &format!("duplicate {} stage", pair[0].typ.as_str()),
+            ));
          }
+      }
+      let is_compute = stages.iter().any(|s| s.typ == ShaderSrcType::Compute);
+      if is_compute && stages.len() > 1 {
+         return Err(GLueError::from(
+            GLueErrorKind::MissingSrc,
+            "compute shaders cannot be linked with other stages",
+         ));
+      }
+      Ok(ShaderFile {
+         path: path.map(|p| p.to_string()),
+         sources,
+         stages,
+         defines: Vec::new(),
+         is_compute,
+         origin: None,
+         hot_reload: false,
+      })
+   }
 
-         GLSL::ParsedPipeline { v_src, f_src } => Ok(ShaderFile::Pipe { v_src, f_src }),
-         GLSL::ParsedCompute(src) => Ok(ShaderFile::Comp(src)),
+   // one module, one entry point per stage, constants are (constant id, value bits)
+   pub fn from_spirv(
+      bytes: &[u8],
+      entry_points: &[(ShaderSrcType, &str)],
+      specialization_constants: &[(u32, u32)],
+   ) -> Result<ShaderFile, GLueError> {
+      match check_spirv_module(bytes) {
+         Err(e) => return Err(e),
+         Ok(()) => {}
+      }
+      if entry_points.is_empty() {
+         return Err(GLueError::from(
+            GLueErrorKind::MissingSrc,
+            "missing spir-v entry points",
+         ));
       }
+      let module = Rc::new(bytes.to_vec());
+      let constants = Rc::new(specialization_constants.to_vec());
+      let stages = entry_points
+         .iter()
+         .map(|(typ, entry)| {
+            let spirv = SpirvStage {
+               module: module.clone(),
+               entry: entry.to_string(),
+               constants: constants.clone(),
+            };
+            StageSrc::from_spirv(*typ, spirv)
+         })
+         .collect();
+      ShaderFile::from_stages(None, vec![None], stages)
    }
 
-   pub fn compile(self) -> Result<Shader, GLueError> {
-      let (src1, src2, is_compute) = match self {
-         ShaderFile::Pipe { v_src, f_src } => (v_src, Some(f_src), false),
-         ShaderFile::Comp(src) => (src, None, true),
+   pub fn from_src(src: &str, typ: ShaderType) -> Result<ShaderFile, GLueError> {
+      ShaderFile::parse(src, typ, None)
+   }
+
+   fn parse(src: &str, typ: ShaderType, path: Option<&str>) -> Result<ShaderFile, GLueError> {
+      let stage_lines = match GLSL::parse(&src, typ) {
+         GLSL::Failed(missing) => {
+            let missing: Vec<&str> = missing.iter().map(|t| t.ex()).collect();
+            let err = GLueError::from(
+               GLueErrorKind::MissingSrc,
+               &format!("missing {}", missing.join(" + ")),
+            );
+            return match path {
+               None => Err(err),
+               Some(path) => Err(err.with_path(path)),
+            };
+         }
+         GLSL::Parsed(stage_lines) => stage_lines,
       };
 
-      let id = match link_program(&src1, &src2, is_compute) {
+      let mut sources = vec![path.map(|p| p.to_string())];
+      let mut stages = Vec::new();
+      for (typ, lines) in stage_lines {
+         let lines = match Preprocessor::new(&mut sources).expand(lines) {
+            Err(e) => return Err(e),
+            Ok(lines) => lines,
+         };
+         stages.push(StageSrc::from_lines(typ, &lines));
+      }
+      ShaderFile::from_stages(path, sources, stages)
+   }
+
+   // glsl only, compiling a spir-v file with defines fails
+   pub fn with_define(mut self, name: &str, value: &str) -> Self {
+      match self.defines.iter_mut().find(|(n, _)| n == name) {
+         Some((_, v)) => *v = value.to_string(),
+         None => self.defines.push((name.to_string(), value.to_string())),
+      }
+      self
+   }
+   pub fn with_defines(mut self, defines: &[(&str, &str)]) -> Self {
+      for (name, value) in defines {
+         self = self.with_define(name, value);
+      }
+      self
+   }
+   pub fn defines(&self) -> &[(String, String)] {
+      &self.defines
+   }
+
+   // same sources and same defines in any order give the same key, hot reloading shaders are
+   // kept apart since they carry their own watch list
+   pub(crate) fn variant_key(&self) -> u64 {
+      let mut defines = self.defines.clone();
+      defines.sort();
+      let mut hasher = DefaultHasher::new();
+      self.is_compute.hash(&mut hasher);
+      self.hot_reload.hash(&mut hasher);
+      for stage in self.stages.iter() {
+         stage.typ.as_str().hash(&mut hasher);
+         stage.src.hash(&mut hasher);
+         match &stage.spirv {
+            None => {}
+            Some(spirv) => {
+               spirv.module.hash(&mut hasher);
+               spirv.entry.hash(&mut hasher);
+               spirv.constants.hash(&mut hasher);
+            }
+         }
+      }
+      defines.hash(&mut hasher);
+      hasher.finish()
+   }
+
+   // like variant_key but built from raw bytes with fnv-1a so it stays the same across rust
+   // releases, used to name files on disk
+   pub(crate) fn stable_key(&self, driver: &str) -> u64 {
+      let mut defines = self.defines.clone();
+      defines.sort();
+      let mut hash = FNV_OFFSET;
+      hash = fnv1a(hash, &[self.is_compute as u8]);
+      for stage in self.stages.iter() {
+         hash = fnv1a(hash, stage.typ.ex().as_bytes());
+         hash = fnv1a(hash, stage.src.as_bytes());
+         match &stage.spirv {
+            None => {}
+            Some(spirv) => {
+               hash = fnv1a(hash, &spirv.module);
+               hash = fnv1a(hash, spirv.entry.as_bytes());
+               for (id, value) in spirv.constants.iter() {
+                  hash = fnv1a(hash, &id.to_le_bytes());
+                  hash = fnv1a(hash, &value.to_le_bytes());
+               }
+            }
+         }
+      }
+      for (name, value) in defines.iter() {
+         hash = fnv1a(hash, name.as_bytes());
+         hash = fnv1a(hash, value.as_bytes());
+      }
+      fnv1a(hash, driver.as_bytes())
+   }
+
+   pub fn add_include(name: &str, src: &str) {
+      add_include(name, src)
+   }
+   pub fn remove_include(name: &str) -> bool {
+      remove_include(name)
+   }
+   pub fn clear_includes() {
+      clear_includes()
+   }
+
+   pub fn path(&self) -> Option<&str> {
+      self.path.as_deref()
+   }
+   pub fn is_compute(&self) -> bool {
+      self.is_compute
+   }
+   pub fn stages(&self) -> Vec<ShaderSrcType> {
+      self.stages.iter().map(|s| s.typ).collect()
+   }
+   pub fn has_stage(&self, typ: ShaderSrcType) -> bool {
+      self.stages.iter().any(|s| s.typ == typ)
+   }
+
+   // files read from disk are watched after compile, see Shader::reload_if_changed
+   pub fn with_hot_reload(mut self) -> Self {
+      self.hot_reload = true;
+      self
+   }
+   pub fn is_hot_reload(&self) -> bool {
+      self.hot_reload
+   }
+
+   pub fn compile(self) -> Result<Shader, GLueError> {
+      let id = match link_program(&self.stages, &self.defines, &self.sources, false) {
          Err(e) => return Err(e),
          Ok(id) => id,
       };
 
-      let shader = Shader {
-         workers: Workers::one(),
-         id,
-         is_compute,
-         tex_ids: vec![None; Slot::total_slots()],
-         sbo_ids: vec![None; Slot::total_slots()],
+      Ok(self.into_shader(id))
+   }
+
+   fn into_shader(self, id: u32) -> Shader {
+      let mut shader = Shader::from_program(id, self.is_compute);
+      shader.hot_reload = match (self.hot_reload, &self.origin) {
+         (true, Some(origin)) => Some(Rc::new(HotReload::new(&self, origin.clone()))),
+         _ => None,
       };
-      Ok(shader)
+      shader
+   }
+}
+
+fn is_spirv_path(path: &str) -> bool {
+   match file::ex(path) {
+      None => false,
+      Some(ex) => ex.to_lowercase() == "spv",
+   }
+}
+
+// the stage of a spir-v file comes from the extension before .spv
+fn spirv_stage(path: &str) -> Option<ShaderSrcType> {
+   let stem = match path.to_lowercase().strip_suffix(".spv") {
+      None => return None,
+      Some(stem) => stem.to_string(),
+   };
+   match file::ex(&stem) {
+      None => None,
+      Some(ex) => ShaderSrcType::from_ex(&ex),
+   }
+}
+
+// pulls #version and #extension out so headers can be rebuilt in the order glsl wants
+// (#version, #extension, then everything else), each one leaves a blank line behind
+fn split_header(src: &str) -> (Option<String>, Vec<String>, String) {
+   let mut version = None;
+   let mut extensions = Vec::new();
+   let mut body = String::with_capacity(src.len());
+   for line in src.lines() {
+      let trimmed = line.trim_start();
+      if version.is_none() && trimmed.starts_with("#version") {
+         version = Some(trimmed.to_string());
+      } else if trimmed.starts_with("#extension") {
+         extensions.push(trimmed.to_string());
+      } else {
+         body.push_str(line);
+      }
+      body.push_str("\n");
+   }
+   (version, extensions, body)
+}
+
+fn es_src(src: &str, ctx: &Ctx) -> String {
+   let (_, extensions, body) = split_header(src);
+   let mut es_src = format!("#version {}{}0 es\n", ctx.major, ctx.minor);
+   for extension in extensions {
+      es_src.push_str(&extension);
+      es_src.push_str("\n");
+   }
+   es_src.push_str("precision highp float;\nprecision highp int;\n");
+   es_src.push_str("precision highp sampler2D;\n");
+   if ctx.at_least(3, 1) {
+      es_src.push_str("precision highp image2D;\n");
    }
+   es_src.push_str(&body);
+   es_src
 }
 
-fn compile_shader(src: &str, typ: ShaderSrcType) -> Result<u32, GLueError> {
+fn inject_defines(src: &str, defines: &[(String, String)]) -> String {
+   if defines.is_empty() {
+      return src.to_string();
+   }
+   let (version, extensions, body) = split_header(src);
+   let mut out = String::with_capacity(src.len());
+   for line in version.iter().chain(extensions.iter()) {
+      out.push_str(line);
+      out.push_str("\n");
+   }
+   for (name, value) in defines {
+      out.push_str(&format!("#define {name} {value}\n"));
+   }
+   out.push_str(&body);
+   out
+}
+
+fn compile_shader(
+   stage: &StageSrc,
+   defines: &[(String, String)],
+   sources: &[Option<String>],
+) -> Result<u32, GLueError> {
+   let ctx = Ctx::current();
+   let (need_gl, need_es) = match stage.typ {
+      ShaderSrcType::Compute => ((4, 3), (3, 1)),
+      ShaderSrcType::Geom => ((3, 2), (3, 2)),
+      ShaderSrcType::TessCtrl | ShaderSrcType::TessEval => ((4, 0), (3, 2)),
+      ShaderSrcType::Vert | ShaderSrcType::Frag => ((2, 0), (2, 0)),
+   };
+   let (major, minor, api) = match ctx.is_es() {
+      true => (need_es.0, need_es.1, "opengl es"),
+      false => (need_gl.0, need_gl.1, "opengl"),
+   };
+   if !ctx.at_least(major, minor) {
+      return Err(GLueError::from(
+         GLueErrorKind::Unsupported,
+         &format!("{} shaders need {api} {major}.{minor}", stage.typ.as_str()),
+      ));
+   }
+   match &stage.spirv {
+      None => {}
+      Some(spirv) => {
+         // spir-v is already compiled, specialization constants take the place of defines
+         if !defines.is_empty() {
+            return Err(GLueError::from(
+               GLueErrorKind::Unsupported,
+               "defines cannot be applied to spir-v stages, use specialization constants",
+            ));
+         }
+         match check_spirv_support() {
+            Err(e) => return Err(e),
+            Ok(()) => {}
+         }
+         let shader_id = match compile_spirv_shader(gl_match_shader_type(&stage.typ), spirv) {
+            Err(e) => return Err(e),
+            Ok(id) => id,
+         };
+         return match unsafe { shader_compile_failure(shader_id, stage, sources) } {
+            Err(e) => {
+               delete_shader(shader_id);
+               Err(e)
+            }
+            Ok(()) => Ok(shader_id),
+         };
+      }
+   }
+   let src = inject_defines(&stage.src, defines);
+   let src = match ctx.is_es() {
+      true => es_src(&src, &ctx),
+      false => src,
+   };
    let src = match CString::new(src) {
       Err(e) => return Err(GLueError::wtf(&format!("c-string failed! {e}"))),
       Ok(s) => s,
    };
    unsafe {
-      let shader_id = gl::CreateShader(gl_match_shader_type(&typ));
+      let shader_id = gl::CreateShader(gl_match_shader_type(&stage.typ));
       gl::ShaderSource(shader_id, 1, &src.as_ptr(), ptr::null());
       gl::CompileShader(shader_id);
 
-      match shader_compile_failure(shader_id, typ) {
+      match shader_compile_failure(shader_id, stage, sources) {
          Ok(()) => Ok(shader_id as u32),
-         Err(e) => Err(e),
+         Err(e) => {
+            delete_shader(shader_id);
+            Err(e)
+         }
       }
    }
 }
 
-fn link_program(src1: &str, src2: &Option<String>, is_compute: bool) -> Result<u32, GLueError> {
-   let v = match is_compute {
-      false => ShaderSrcType::Vert,
-      true => ShaderSrcType::Compute,
-   };
-
+fn link_program(
+   stages: &[StageSrc],
+   defines: &[(String, String)],
+   sources: &[Option<String>],
+   retrievable: bool,
+) -> Result<u32, GLueError> {
    unsafe {
       let program_id = gl::CreateProgram();
-      let v_shader_id = match compile_shader(src1, v) {
-         Err(e) => return Err(e),
-         Ok(vs_id) => vs_id,
-      };
-      let mut f_shader_id = 0;
-      gl::AttachShader(program_id, v_shader_id);
-      match src2 {
-         None => {}
-         Some(frag) => {
-            f_shader_id = match compile_shader(frag, ShaderSrcType::Frag) {
-               Err(e) => return Err(e),
-               Ok(fs_id) => fs_id,
-            };
-            gl::AttachShader(program_id, f_shader_id);
-         }
+      if retrievable {
+         gl::ProgramParameteri(
+            program_id,
+            gl::PROGRAM_BINARY_RETRIEVABLE_HINT,
+            gl::TRUE as GLint,
+         );
+      }
+      let mut shader_ids = Vec::with_capacity(stages.len());
+      for stage in stages {
+         let shader_id = match compile_shader(stage, defines, sources) {
+            Err(e) => {
+               for shader_id in shader_ids {
+                  delete_shader(shader_id);
+               }
+               delete_program(program_id);
+               return Err(e);
+            }
+            Ok(id) => id,
+         };
+         gl::AttachShader(program_id, shader_id);
+         shader_ids.push(shader_id);
       }
       gl::LinkProgram(program_id);
 
+      for shader_id in shader_ids {
+         delete_shader(shader_id);
+      }
       match program_link_failure(program_id) {
-         Err(e) => Err(e),
-         Ok(()) => {
-            delete_shader(v_shader_id);
-            if src2.is_some() {
-               delete_shader(f_shader_id);
-            }
-            Ok(program_id as u32)
+         Err(e) => {
+            delete_program(program_id);
+            Err(e)
+         }
+         Ok(()) => Ok(program_id as u32),
+      }
+   }
+}
+
+pub struct ShaderCache {
+   variants: HashMap<u64, Shader>,
+   binary_dir: Option<String>,
+   driver: String,
+   write_errors: Vec<GLueError>,
+}
+
+impl ShaderCache {
+   pub fn new() -> ShaderCache {
+      ShaderCache {
+         variants: HashMap::new(),
+         binary_dir: None,
+         driver: String::new(),
+         write_errors: Vec::new(),
+      }
+   }
+
+   // linked programs are also kept as driver binaries in dir, a new driver or gpu misses the cache,
+   // contexts without program binary support (gl < 4.1 without ARB_get_program_binary) skip it
+   pub fn with_binary_cache(mut self, dir: &str, gpu: &GPU) -> Self {
+      let mut dir = dir.to_string();
+      if !dir.ends_with('/') {
+         dir.push('/');
+      }
+      self.binary_dir = Some(dir);
+      self.driver = format!("{} {} {}", gpu.vendor(), gpu.name(), gpu.version());
+      self
+   }
+   pub fn binary_dir(&self) -> Option<&str> {
+      self.binary_dir.as_deref()
+   }
+
+   pub fn compile(&mut self, file: ShaderFile) -> Result<Shader, GLueError> {
+      let key = file.variant_key();
+      match self.variants.get(&key) {
+         Some(shader) => return Ok(shader.clone()),
+         None => {}
+      }
+      let shader = match &self.binary_dir {
+         Some(dir) if program_binary_supported() => {
+            compile_with_binary(file, dir, &self.driver, &mut self.write_errors)
+         }
+         _ => file.compile(),
+      };
+      let shader = match shader {
+         Err(e) => return Err(e),
+         Ok(shader) => shader,
+      };
+      self.variants.insert(key, shader.clone());
+      Ok(shader)
+   }
+
+   pub fn contains(&self, file: &ShaderFile) -> bool {
+      self.variants.contains_key(&file.variant_key())
+   }
+   pub fn len(&self) -> usize {
+      self.variants.len()
+   }
+   pub fn is_empty(&self) -> bool {
+      self.variants.is_empty()
+   }
+   pub fn clear(&mut self) {
+      self.variants.clear()
+   }
+
+   // binaries that could not be written to disk, the shaders themselves compiled fine
+   pub fn take_write_errors(&mut self) -> Vec<GLueError> {
+      std::mem::take(&mut self.write_errors)
+   }
+}
+
+impl Default for ShaderCache {
+   fn default() -> ShaderCache {
+      ShaderCache::new()
+   }
+}
+
+// a rejected or unreadable binary falls back to compiling the sources and rewriting it
+fn compile_with_binary(
+   file: ShaderFile,
+   dir: &str,
+   driver: &str,
+   write_errors: &mut Vec<GLueError>,
+) -> Result<Shader, GLueError> {
+   let name = format!("{:016x}.bin", file.stable_key(driver));
+   let path = format!("{dir}{name}");
+
+   if file::exists_on_disk(&path) {
+      match file::read_as_bytes(&path) {
+         Err(_) => {}
+         Ok(bytes) => match load_program_binary(&bytes) {
+            None => {}
+            Some(id) => return Ok(file.into_shader(id)),
+         },
+      }
+   }
+
+   let id = match link_program(&file.stages, &file.defines, &file.sources, true) {
+      Err(e) => return Err(e),
+      Ok(id) => id,
+   };
+   match get_program_binary(id) {
+      None => {}
+      Some(bytes) => match file::write_bytes_to_disk(dir, &name, &bytes) {
+         Err(e) => write_errors.push(e),
+         Ok(()) => {}
+      },
+   }
+   Ok(file.into_shader(id))
+}
+
+//PROGRAM BINARY
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// each field is length prefixed so ("ab", "c") and ("a", "bc") hash differently
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+   for byte in (bytes.len() as u64)
+      .to_le_bytes()
+      .iter()
+      .chain(bytes.iter())
+   {
+      hash ^= *byte as u64;
+      hash = hash.wrapping_mul(FNV_PRIME);
+   }
+   hash
+}
+
+fn program_binary_supported() -> bool {
+   let ctx = Ctx::current();
+   let core = match ctx.is_es() {
+      true => ctx.at_least(3, 0),
+      false => ctx.at_least(4, 1),
+   };
+   let loaded = gl::ProgramParameteri::is_loaded()
+      && gl::ProgramBinary::is_loaded()
+      && gl::GetProgramBinary::is_loaded();
+   match (core || Caps::query().has_extension("GL_ARB_get_program_binary")) && loaded {
+      true => !program_binary_formats().is_empty(),
+      false => false,
+   }
+}
+
+fn program_binary_formats() -> Vec<GLenum> {
+   let mut count = 0;
+   unsafe {
+      gl::GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut count);
+   }
+   let mut formats = vec![0 as GLint; count.max(0) as usize];
+   if count > 0 {
+      unsafe {
+         gl::GetIntegerv(gl::PROGRAM_BINARY_FORMATS, formats.as_mut_ptr());
+      }
+   }
+   formats.iter().map(|f| *f as GLenum).collect()
+}
+
+// stored as the binary format (u32 le) followed by the driver blob
+fn get_program_binary(program: u32) -> Option<Vec<u8>> {
+   if program_binary_formats().is_empty() {
+      return None;
+   }
+   let mut len = 0;
+   unsafe {
+      gl::GetProgramiv(program, gl::PROGRAM_BINARY_LENGTH, &mut len);
+   }
+   if len <= 0 {
+      return None;
+   }
+   let mut format: GLenum = 0;
+   let mut written: GLsizei = 0;
+   let mut blob = vec![0u8; len as usize];
+   unsafe {
+      gl::GetProgramBinary(
+         program,
+         len,
+         &mut written,
+         &mut format,
+         blob.as_mut_ptr() as *mut _,
+      );
+   }
+   if written <= 0 {
+      return None;
+   }
+   blob.truncate(written as usize);
+   let mut bytes = format.to_le_bytes().to_vec();
+   bytes.extend_from_slice(&blob);
+   Some(bytes)
+}
+
+fn load_program_binary(bytes: &[u8]) -> Option<u32> {
+   if bytes.len() <= 4 {
+      return None;
+   }
+   let format = u32::from_le_bytes(clone_slice_4(&bytes[0..4]));
+   if !program_binary_formats().contains(&format) {
+      return None;
+   }
+   let blob = &bytes[4..];
+   unsafe {
+      let program_id = gl::CreateProgram();
+      // a stale binary is expected after driver updates, keep it out of the debug queue
+      let success = without_debug_output(|| {
+         gl::ProgramBinary(
+            program_id,
+            format,
+            blob.as_ptr() as *const _,
+            blob.len() as GLsizei,
+         );
+         let mut success = gl::FALSE as GLint;
+         gl::GetProgramiv(program_id, gl::LINK_STATUS, &mut success);
+         success
+      });
+      match success == gl::TRUE as GLint {
+         true => Some(program_id),
+         false => {
+            delete_program(program_id);
+            None
          }
       }
    }
@@ -267,12 +1049,19 @@ fn u32_to_vec_of_4_u8s(n: u32) -> Vec<u8> {
 fn gl_match_shader_type(t: &ShaderSrcType) -> GLenum {
    match t {
       ShaderSrcType::Vert => gl::VERTEX_SHADER,
+      ShaderSrcType::TessCtrl => gl::TESS_CONTROL_SHADER,
+      ShaderSrcType::TessEval => gl::TESS_EVALUATION_SHADER,
+      ShaderSrcType::Geom => gl::GEOMETRY_SHADER,
       ShaderSrcType::Frag => gl::FRAGMENT_SHADER,
       ShaderSrcType::Compute => gl::COMPUTE_SHADER,
    }
 }
 
-unsafe fn shader_compile_failure(shader: GLuint, typ: ShaderSrcType) -> Result<(), GLueError> {
+unsafe fn shader_compile_failure(
+   shader: GLuint,
+   stage: &StageSrc,
+   sources: &[Option<String>],
+) -> Result<(), GLueError> {
    let mut success = gl::FALSE as GLint;
    gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
    if success != gl::TRUE as GLint {
@@ -290,17 +1079,23 @@ unsafe fn shader_compile_failure(shader: GLuint, typ: ShaderSrcType) -> Result<(
       let log = str::from_utf8(&log)
          .unwrap_or("unreachable-log")
          .to_string();
-      Err(GLueError::from(
-         GLueErrorKind::ShaderCompileFailed,
-         &format!(
-            "{} shader compile failed: {log}",
-            match typ {
-               ShaderSrcType::Vert => "vertex",
-               ShaderSrcType::Frag => "fragment",
-               ShaderSrcType::Compute => "compute",
+
+      let diagnostics = map_info_log(&log, sources);
+      let mut msg = format!("{} shader compile failed:", stage.typ.as_str());
+      match diagnostics.is_empty() {
+         true => msg.push_str(&format!(" {log}")),
+         false => {
+            for diagnostic in diagnostics.iter() {
+               msg.push_str(&format!("\n{diagnostic}"));
             }
-         ),
-      ))
+         }
+      }
+      let err = GLueError::from(GLueErrorKind::ShaderCompileFailed, &msg);
+      let err = match sources.first() {
+         Some(Some(path)) => err.with_path(path),
+         _ => err,
+      };
+      Err(err.with_diagnostics(diagnostics))
    } else {
       Ok(())
    }
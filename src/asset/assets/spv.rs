@@ -0,0 +1,94 @@
+use crate::*;
+use gl::types::{GLchar, GLenum, GLsizei, GLuint};
+use std::cell::Cell;
+use std::ffi::{CString, c_void};
+use std::rc::Rc;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+const SHADER_BINARY_FORMAT_SPIR_V: GLenum = 0x9551;
+
+// the gl crate predates 4.6, so glSpecializeShader is loaded by hand alongside the rest
+type SpecializeShaderFn =
+   unsafe extern "system" fn(GLuint, *const GLchar, GLuint, *const GLuint, *const GLuint);
+
+thread_local! {
+   static SPECIALIZE_SHADER: Cell<Option<SpecializeShaderFn>> = const { Cell::new(None) };
+}
+
+pub(crate) fn load_spirv_fns<F: FnMut(&str) -> *const c_void>(mut load: F) {
+   let ptr = match load("glSpecializeShader") {
+      p if !p.is_null() => p,
+      _ => load("glSpecializeShaderARB"),
+   };
+   let func = match ptr.is_null() {
+      true => None,
+      false => Some(unsafe { std::mem::transmute::<*const c_void, SpecializeShaderFn>(ptr) }),
+   };
+   SPECIALIZE_SHADER.with(|f| f.set(func));
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct SpirvStage {
+   pub(crate) module: Rc<Vec<u8>>,
+   pub(crate) entry: String,
+   pub(crate) constants: Rc<Vec<(u32, u32)>>,
+}
+
+pub(crate) fn check_spirv_module(bytes: &[u8]) -> Result<(), GLueError> {
+   let magic = match bytes.get(0..4) {
+      None => 0,
+      Some(b) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+   };
+   match bytes.len() % 4 == 0 && (magic == SPIRV_MAGIC || magic.swap_bytes() == SPIRV_MAGIC) {
+      true => Ok(()),
+      false => Err(GLueError::from(
+         GLueErrorKind::WierdFile,
+         "not a spir-v module",
+      )),
+   }
+}
+
+pub(crate) fn check_spirv_support() -> Result<(), GLueError> {
+   let loaded = SPECIALIZE_SHADER.with(|f| f.get()).is_some();
+   match Caps::query().has_spirv() && loaded {
+      true => Ok(()),
+      false => Err(GLueError::from(
+         GLueErrorKind::Unsupported,
+         "spir-v shaders need opengl 4.6 or GL_ARB_gl_spirv",
+      )),
+   }
+}
+
+pub(crate) fn compile_spirv_shader(
+   shader_type: GLenum,
+   spirv: &SpirvStage,
+) -> Result<u32, GLueError> {
+   let entry = match CString::new(spirv.entry.as_str()) {
+      Err(e) => return Err(GLueError::wtf(&format!("c-string failed! {e}"))),
+      Ok(s) => s,
+   };
+   let indices: Vec<u32> = spirv.constants.iter().map(|(i, _)| *i).collect();
+   let values: Vec<u32> = spirv.constants.iter().map(|(_, v)| *v).collect();
+   let specialize = match SPECIALIZE_SHADER.with(|f| f.get()) {
+      None => return Err(GLueError::wtf("glSpecializeShader is not loaded")),
+      Some(f) => f,
+   };
+   unsafe {
+      let shader_id = gl::CreateShader(shader_type);
+      gl::ShaderBinary(
+         1,
+         &shader_id,
+         SHADER_BINARY_FORMAT_SPIR_V,
+         spirv.module.as_ptr() as *const _,
+         spirv.module.len() as GLsizei,
+      );
+      specialize(
+         shader_id,
+         entry.as_ptr(),
+         indices.len() as u32,
+         indices.as_ptr(),
+         values.as_ptr(),
+      );
+      Ok(shader_id)
+   }
+}
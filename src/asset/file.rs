@@ -2,6 +2,7 @@ use crate::{GLueError, GLueErrorKind};
 use std::fs;
 use std::io::{ErrorKind, Read, Write};
 use std::path::PathBuf;
+use std::time::SystemTime;
 
 pub(crate) fn name(path: &str) -> Option<String> {
    let path = PathBuf::from(&path);
@@ -19,11 +20,26 @@ pub(crate) fn ex(path: &str) -> Option<String> {
    }
 }
 
+pub(crate) fn dir(path: &str) -> String {
+   let path = PathBuf::from(&path);
+   match path.parent() {
+      Some(p) if !p.as_os_str().is_empty() => format!("{}/", p.to_string_lossy()),
+      _ => String::new(),
+   }
+}
+
 pub(crate) fn exists_on_disk(path: &str) -> bool {
    let path = PathBuf::from(&path);
    path.exists()
 }
 
+pub(crate) fn modified(path: &str) -> Option<SystemTime> {
+   match fs::metadata(path) {
+      Err(_) => None,
+      Ok(meta) => meta.modified().ok(),
+   }
+}
+
 pub(crate) fn write_str_to_disk(path: &str, name: &str, content: &str) -> Result<(), GLueError> {
    write_bytes_to_disk(path, name, content.as_bytes())
 }
@@ -34,10 +50,14 @@ pub(crate) fn write_bytes_to_disk(path: &str, name: &str, content: &[u8]) -> Res
    if !pathbuf.exists() {
       match fs::create_dir_all(path) {
          Err(e) => {
-            return Err(GLueError::from(
-               GLueErrorKind::CouldNotMake,
-               &format!("could not make dir {path} {e}"),
-            ));
+            return Err(
+               GLueError::from(
+                  GLueErrorKind::CouldNotMake,
+                  &format!("could not make dir {path} {e}"),
+               )
+               .with_path(path)
+               .with_source(e),
+            );
          }
          Ok(_) => {}
       };
@@ -47,25 +67,33 @@ pub(crate) fn write_bytes_to_disk(path: &str, name: &str, content: &[u8]) -> Res
    let mut file = match fs::File::create(&file_path) {
       Ok(f) => f,
       Err(e) => {
-         return Err(GLueError::from(
-            GLueErrorKind::CouldNotMake,
-            &format!("could not make dir {file_path} {e}"),
-         ));
+         return Err(
+            GLueError::from(
+               GLueErrorKind::CouldNotMake,
+               &format!("could not make dir {file_path} {e}"),
+            )
+            .with_path(&file_path)
+            .with_source(e),
+         );
       }
    };
    match file.write_all(content) {
       Ok(_) => Ok(()),
-      Err(e) => Err(GLueError::from(
-         GLueErrorKind::CouldNotWrite,
-         &format!("could not write file {file_path} {e}"),
-      )),
+      Err(e) => Err(
+         GLueError::from(
+            GLueErrorKind::CouldNotWrite,
+            &format!("could not write file {file_path} {e}"),
+         )
+         .with_path(&file_path)
+         .with_source(e),
+      ),
    }
 }
 
 pub(crate) fn read_as_bytes(path: &str) -> Result<Vec<u8>, GLueError> {
    let mut contents: Vec<u8> = Vec::new();
 
-   let mut err;
+   let err;
    match fs::File::open(&path) {
       Ok(mut file) => match file.read_to_end(&mut contents) {
          Ok(_) => return Ok(contents),
@@ -85,13 +113,13 @@ pub(crate) fn read_as_bytes(path: &str) -> Result<Vec<u8>, GLueError> {
       ),
       e => GLueError::wtf(&format!("unknown file error {e}")),
    };
-   Err(glue_err)
+   Err(glue_err.with_path(path).with_source(err))
 }
 
 pub(crate) fn read_as_string(path: &str) -> Result<String, GLueError> {
    let mut contents = String::new();
 
-   let mut err;
+   let err;
    match fs::File::open(&path) {
       Ok(mut file) => match file.read_to_string(&mut contents) {
          Ok(_) => return Ok(contents),
@@ -113,5 +141,5 @@ pub(crate) fn read_as_string(path: &str) -> Result<String, GLueError> {
       ),
       e => GLueError::wtf(&format!("unknown file error {err}")),
    };
-   Err(glue_err)
+   Err(glue_err.with_path(path).with_source(err))
 }
@@ -1,7 +1,7 @@
 use crate::CamTransform;
 use cgmath::*;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Size2D {
     pub w: u32,
     pub h: u32,
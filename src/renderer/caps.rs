@@ -0,0 +1,103 @@
+use crate::renderer::glraw::{Ctx, GL_SPV_EXTENSION, SPIRV_EXTENSIONS};
+use gl::types::{GLenum, GLint, GLint64};
+use std::ffi::CStr;
+
+#[derive(Clone, Debug)]
+pub struct Caps {
+   pub extensions: Vec<String>,
+   pub max_texture_size: u32,
+   pub max_samples: u32,
+   pub max_vertex_attribs: u32,
+   pub max_ssbo_bindings: u32,
+   pub max_ssbo_size: u64,
+   pub max_compute_work_group_count: [u32; 3],
+   pub max_compute_work_group_size: [u32; 3],
+   pub max_compute_invocations: u32,
+}
+
+impl Caps {
+   pub(crate) fn query() -> Caps {
+      let ctx = Ctx::current();
+      let has_compute = match ctx.is_es() {
+         true => ctx.at_least(3, 1),
+         false => ctx.at_least(4, 3),
+      };
+
+      let mut caps = Caps {
+         extensions: query_extensions(),
+         max_texture_size: get_u32(gl::MAX_TEXTURE_SIZE),
+         max_samples: get_u32(gl::MAX_SAMPLES),
+         max_vertex_attribs: get_u32(gl::MAX_VERTEX_ATTRIBS),
+         max_ssbo_bindings: 0,
+         max_ssbo_size: 0,
+         max_compute_work_group_count: [0; 3],
+         max_compute_work_group_size: [0; 3],
+         max_compute_invocations: 0,
+      };
+      if has_compute {
+         caps.max_ssbo_bindings = get_u32(gl::MAX_SHADER_STORAGE_BUFFER_BINDINGS);
+         caps.max_ssbo_size = get_u64(gl::MAX_SHADER_STORAGE_BLOCK_SIZE);
+         for i in 0..3 {
+            caps.max_compute_work_group_count[i] =
+               get_indexed_u32(gl::MAX_COMPUTE_WORK_GROUP_COUNT, i as u32);
+            caps.max_compute_work_group_size[i] =
+               get_indexed_u32(gl::MAX_COMPUTE_WORK_GROUP_SIZE, i as u32);
+         }
+         caps.max_compute_invocations = get_u32(gl::MAX_COMPUTE_WORK_GROUP_INVOCATIONS);
+      }
+      caps
+   }
+
+   pub fn has_extension(&self, name: &str) -> bool {
+      self.extensions.iter().any(|e| e == name)
+   }
+   pub fn has_compute(&self) -> bool {
+      self.max_compute_invocations > 0
+   }
+   pub fn has_spirv(&self) -> bool {
+      let ctx = Ctx::current();
+      (!ctx.is_es() && ctx.at_least(4, 6)) || self.has_extension(GL_SPV_EXTENSION)
+   }
+   pub fn has_spirv_extensions(&self) -> bool {
+      let ctx = Ctx::current();
+      (!ctx.is_es() && ctx.at_least(4, 6)) || self.has_extension(SPIRV_EXTENSIONS)
+   }
+}
+
+fn query_extensions() -> Vec<String> {
+   let count = get_u32(gl::NUM_EXTENSIONS);
+   let mut extensions = Vec::with_capacity(count as usize);
+   for i in 0..count {
+      let ptr = unsafe { gl::GetStringi(gl::EXTENSIONS, i) };
+      if ptr.is_null() {
+         continue;
+      }
+      let ext = unsafe { CStr::from_ptr(ptr as *const _) };
+      extensions.push(ext.to_string_lossy().to_string());
+   }
+   extensions
+}
+
+fn get_u32(name: GLenum) -> u32 {
+   let mut value: GLint = 0;
+   unsafe {
+      gl::GetIntegerv(name, &mut value);
+   }
+   value.max(0) as u32
+}
+
+fn get_u64(name: GLenum) -> u64 {
+   let mut value: GLint64 = 0;
+   unsafe {
+      gl::GetInteger64v(name, &mut value);
+   }
+   value.max(0) as u64
+}
+
+fn get_indexed_u32(name: GLenum, index: u32) -> u32 {
+   let mut value: GLint = 0;
+   unsafe {
+      gl::GetIntegeri_v(name, index, &mut value);
+   }
+   value.max(0) as u32
+}
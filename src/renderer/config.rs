@@ -0,0 +1,135 @@
+use crate::{Device, Size2D};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GLApi {
+   GL,
+   ES,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GLProfile {
+   Core,
+   Compat,
+}
+
+#[derive(Clone, Debug)]
+pub struct GpuConfig {
+   pub(crate) size: Size2D,
+   pub(crate) device: Option<Device>,
+   pub(crate) api: GLApi,
+   pub(crate) major: u8,
+   pub(crate) minor: u8,
+   // set by with_version, keeps with_api from swapping in its default version
+   pub(crate) explicit_version: bool,
+   pub(crate) profile: GLProfile,
+   pub(crate) debug: bool,
+   pub(crate) robust: bool,
+   pub(crate) depth_bits: u8,
+   pub(crate) stencil_bits: u8,
+   pub(crate) samples: u8,
+}
+
+impl Default for GpuConfig {
+   fn default() -> GpuConfig {
+      GpuConfig {
+         size: Size2D::from(10, 10),
+         device: None,
+         api: GLApi::GL,
+         major: 3,
+         minor: 3,
+         explicit_version: false,
+         profile: GLProfile::Core,
+         debug: false,
+         robust: false,
+         depth_bits: 24,
+         stencil_bits: 0,
+         samples: 0,
+      }
+   }
+}
+
+impl GpuConfig {
+   pub fn new() -> GpuConfig {
+      GpuConfig::default()
+   }
+
+   pub fn with_size(mut self, size: Size2D) -> GpuConfig {
+      self.size = size;
+      self
+   }
+   pub fn with_device(mut self, device: Device) -> GpuConfig {
+      self.device = Some(device);
+      self
+   }
+   pub fn with_api(mut self, api: GLApi) -> GpuConfig {
+      if self.api != api && !self.explicit_version {
+         (self.major, self.minor) = match api {
+            GLApi::GL => (3, 3),
+            GLApi::ES => (3, 1),
+         };
+      }
+      self.api = api;
+      self
+   }
+   pub fn with_version(mut self, major: u8, minor: u8) -> GpuConfig {
+      self.major = major;
+      self.minor = minor;
+      self.explicit_version = true;
+      self
+   }
+   pub fn with_profile(mut self, profile: GLProfile) -> GpuConfig {
+      self.profile = profile;
+      self
+   }
+   pub fn with_debug(mut self, enable: bool) -> GpuConfig {
+      self.debug = enable;
+      self
+   }
+   pub fn with_robustness(mut self, enable: bool) -> GpuConfig {
+      self.robust = enable;
+      self
+   }
+   pub fn with_depth_bits(mut self, bits: u8) -> GpuConfig {
+      self.depth_bits = bits;
+      self
+   }
+   pub fn with_stencil_bits(mut self, bits: u8) -> GpuConfig {
+      self.stencil_bits = bits;
+      self
+   }
+   pub fn with_samples(mut self, samples: u8) -> GpuConfig {
+      self.samples = samples;
+      self
+   }
+
+   pub fn size(&self) -> Size2D {
+      self.size
+   }
+   pub fn device(&self) -> Option<&Device> {
+      self.device.as_ref()
+   }
+   pub fn api(&self) -> GLApi {
+      self.api
+   }
+   pub fn version(&self) -> (u8, u8) {
+      (self.major, self.minor)
+   }
+   pub fn profile(&self) -> GLProfile {
+      self.profile
+   }
+   pub fn is_debug(&self) -> bool {
+      self.debug
+   }
+   pub fn is_robust(&self) -> bool {
+      self.robust
+   }
+   pub fn depth_bits(&self) -> u8 {
+      self.depth_bits
+   }
+   pub fn stencil_bits(&self) -> u8 {
+      self.stencil_bits
+   }
+   pub fn samples(&self) -> u8 {
+      self.samples
+   }
+}
@@ -0,0 +1,234 @@
+use crate::{GLueError, GLueErrorKind};
+use gl::types::{GLchar, GLenum, GLsizei, GLuint};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::{CStr, c_void};
+
+// oldest errors are dropped past this when nobody drains the queue
+pub(crate) const MAX_DEBUG_ERRORS: usize = 256;
+
+thread_local! {
+   static WARNINGS: RefCell<VecDeque<GLueError>> = const { RefCell::new(VecDeque::new()) };
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub enum DebugSeverity {
+   Note,
+   Low,
+   Medium,
+   High,
+}
+
+#[derive(Clone, Debug)]
+pub struct DebugMessage {
+   pub severity: DebugSeverity,
+   pub source: &'static str,
+   pub kind: &'static str,
+   pub id: u32,
+   pub msg: String,
+}
+
+pub(crate) struct DebugState {
+   pub(crate) min_severity: DebugSeverity,
+   pub(crate) sink: Option<Box<dyn FnMut(&DebugMessage)>>,
+   pub(crate) errors: VecDeque<GLueError>,
+}
+
+impl DebugState {
+   pub(crate) fn new(min_severity: DebugSeverity) -> DebugState {
+      DebugState {
+         min_severity,
+         sink: None,
+         errors: VecDeque::new(),
+      }
+   }
+
+   fn push(&mut self, msg: DebugMessage) {
+      if msg.severity < self.min_severity {
+         return;
+      }
+      match &mut self.sink {
+         None => {}
+         Some(sink) => sink(&msg),
+      }
+      if self.errors.len() >= MAX_DEBUG_ERRORS {
+         self.errors.pop_front();
+      }
+      self.errors.push_back(GLueError::from(
+         GLueErrorKind::GLDebug,
+         &format!(
+            "{:?} {} {} #{}: {}",
+            msg.severity, msg.source, msg.kind, msg.id, msg.msg
+         ),
+      ));
+   }
+}
+
+pub(crate) fn enable_debug_output(state: &RefCell<DebugState>) -> Result<(), GLueError> {
+   if !gl::DebugMessageCallback::is_loaded() || !gl::DebugMessageControl::is_loaded() {
+      return Err(GLueError::from(
+         GLueErrorKind::Unsupported,
+         "debug output needs opengl 4.3 or KHR_debug",
+      ));
+   }
+   unsafe {
+      gl::Enable(gl::DEBUG_OUTPUT);
+      gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+      gl::DebugMessageCallback(
+         Some(debug_callback),
+         state as *const RefCell<DebugState> as *mut c_void,
+      );
+      gl::DebugMessageControl(
+         gl::DONT_CARE,
+         gl::DONT_CARE,
+         gl::DONT_CARE,
+         0,
+         std::ptr::null(),
+         gl::TRUE,
+      );
+   }
+   Ok(())
+}
+
+pub(crate) fn disable_debug_output() {
+   if !gl::DebugMessageCallback::is_loaded() {
+      return;
+   }
+   unsafe {
+      gl::DebugMessageCallback(None, std::ptr::null());
+      gl::Disable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+      gl::Disable(gl::DEBUG_OUTPUT);
+   }
+}
+
+pub(crate) fn label_object(identifier: GLenum, id: u32, name: &str) {
+   if !gl::ObjectLabel::is_loaded() || id == 0 {
+      return;
+   }
+   unsafe {
+      gl::ObjectLabel(
+         identifier,
+         id,
+         name.len() as GLsizei,
+         name.as_ptr() as *const GLchar,
+      );
+   }
+}
+
+// library warnings go through the same callback as driver messages so they reach the sink
+// and the error queue, with debug output off they are queued for GPU::drain_warnings instead
+pub(crate) fn debug_warn(err: GLueError) {
+   let debug_on =
+      gl::DebugMessageInsert::is_loaded() && unsafe { gl::IsEnabled(gl::DEBUG_OUTPUT) == gl::TRUE };
+   if !debug_on {
+      WARNINGS.with(|w| {
+         let mut warnings = w.borrow_mut();
+         if warnings.len() >= MAX_DEBUG_ERRORS {
+            warnings.pop_front();
+         }
+         warnings.push_back(err);
+      });
+      return;
+   }
+   let msg = err.msg();
+   let msg = msg.as_str();
+   let mut max_len = 0;
+   unsafe {
+      gl::GetIntegerv(gl::MAX_DEBUG_MESSAGE_LENGTH, &mut max_len);
+   }
+   let mut len = msg.len().min((max_len.max(1) - 1) as usize);
+   while !msg.is_char_boundary(len) {
+      len -= 1;
+   }
+   unsafe {
+      gl::DebugMessageInsert(
+         gl::DEBUG_SOURCE_APPLICATION,
+         gl::DEBUG_TYPE_OTHER,
+         0,
+         gl::DEBUG_SEVERITY_MEDIUM,
+         len as GLsizei,
+         msg.as_ptr() as *const GLchar,
+      );
+   }
+}
+
+pub(crate) fn drain_warnings() -> Vec<GLueError> {
+   WARNINGS.with(|w| w.borrow_mut().drain(..).collect())
+}
+
+// runs f with debug output off and clears the error flag after, for calls whose failure is expected
+pub(crate) fn without_debug_output<R, F: FnOnce() -> R>(f: F) -> R {
+   let enabled = unsafe { gl::IsEnabled(gl::DEBUG_OUTPUT) == gl::TRUE };
+   if enabled {
+      unsafe { gl::Disable(gl::DEBUG_OUTPUT) }
+   }
+   let result = f();
+   unsafe {
+      while gl::GetError() != gl::NO_ERROR {}
+      if enabled {
+         gl::Enable(gl::DEBUG_OUTPUT);
+      }
+   }
+   result
+}
+
+extern "system" fn debug_callback(
+   source: GLenum,
+   gltype: GLenum,
+   id: GLuint,
+   severity: GLenum,
+   _length: GLsizei,
+   message: *const GLchar,
+   user_param: *mut c_void,
+) {
+   if user_param.is_null() || message.is_null() {
+      return;
+   }
+   let state = unsafe { &*(user_param as *const RefCell<DebugState>) };
+   let mut state = match state.try_borrow_mut() {
+      Err(_) => return,
+      Ok(s) => s,
+   };
+   let msg = unsafe { CStr::from_ptr(message) }
+      .to_string_lossy()
+      .to_string();
+   state.push(DebugMessage {
+      severity: match_severity(severity),
+      source: match_source(source),
+      kind: match_kind(gltype),
+      id,
+      msg,
+   });
+}
+
+fn match_severity(severity: GLenum) -> DebugSeverity {
+   match severity {
+      gl::DEBUG_SEVERITY_HIGH => DebugSeverity::High,
+      gl::DEBUG_SEVERITY_MEDIUM => DebugSeverity::Medium,
+      gl::DEBUG_SEVERITY_LOW => DebugSeverity::Low,
+      _ => DebugSeverity::Note,
+   }
+}
+
+fn match_source(source: GLenum) -> &'static str {
+   match source {
+      gl::DEBUG_SOURCE_API => "api",
+      gl::DEBUG_SOURCE_WINDOW_SYSTEM => "window system",
+      gl::DEBUG_SOURCE_SHADER_COMPILER => "shader compiler",
+      gl::DEBUG_SOURCE_THIRD_PARTY => "third party",
+      gl::DEBUG_SOURCE_APPLICATION => "application",
+      _ => "other",
+   }
+}
+
+fn match_kind(gltype: GLenum) -> &'static str {
+   match gltype {
+      gl::DEBUG_TYPE_ERROR => "error",
+      gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated",
+      gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined behavior",
+      gl::DEBUG_TYPE_PORTABILITY => "portability",
+      gl::DEBUG_TYPE_PERFORMANCE => "performance",
+      gl::DEBUG_TYPE_MARKER => "marker",
+      _ => "other",
+   }
+}
@@ -0,0 +1,180 @@
+use crate::{GLueError, GLueErrorKind};
+use khronos_egl as egl;
+use std::ffi::{CStr, c_char, c_void};
+
+const PLATFORM_DEVICE_EXT: egl::Enum = 0x313F;
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+const DRM_DEVICE_FILE_EXT: egl::Int = 0x3233;
+const RENDERER_EXT: egl::Int = 0x335F;
+
+const DEVICE_ENUMERATION_EXTENSION: &str = "EGL_EXT_device_enumeration";
+const DEVICE_BASE_EXTENSION: &str = "EGL_EXT_device_base";
+const DEVICE_QUERY_EXTENSION: &str = "EGL_EXT_device_query";
+const PLATFORM_DEVICE_EXTENSION: &str = "EGL_EXT_platform_device";
+const PLATFORM_SURFACELESS_EXTENSION: &str = "EGL_MESA_platform_surfaceless";
+const DEVICE_QUERY_NAME_EXTENSION: &str = "EGL_EXT_device_query_name";
+pub(crate) const SURFACELESS_CONTEXT_EXTENSION: &str = "EGL_KHR_surfaceless_context";
+
+type QueryDevicesEXT =
+   unsafe extern "system" fn(egl::Int, *mut *mut c_void, *mut egl::Int) -> egl::Boolean;
+type QueryDeviceStringEXT = unsafe extern "system" fn(*mut c_void, egl::Int) -> *const c_char;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DeviceKind {
+   Default,
+   Hardware,
+   Surfaceless,
+}
+
+#[derive(Clone, Debug)]
+pub struct Device {
+   pub(crate) kind: DeviceKind,
+   pub(crate) ptr: usize,
+   pub(crate) name: String,
+   pub(crate) vendor: String,
+   pub(crate) drm_path: Option<String>,
+}
+
+impl Device {
+   pub fn default_display() -> Device {
+      Device {
+         kind: DeviceKind::Default,
+         ptr: 0,
+         name: "default".to_string(),
+         vendor: "unknown".to_string(),
+         drm_path: None,
+      }
+   }
+
+   pub fn kind(&self) -> DeviceKind {
+      self.kind
+   }
+   pub fn name(&self) -> &str {
+      &self.name
+   }
+   pub fn vendor(&self) -> &str {
+      &self.vendor
+   }
+   pub fn drm_path(&self) -> Option<&str> {
+      self.drm_path.as_deref()
+   }
+
+   pub(crate) fn enumerate() -> Result<Vec<Device>, GLueError> {
+      let egl = egl::Instance::new(egl::Static);
+      let client_exts = match egl.query_string(None, egl::EXTENSIONS) {
+         Err(e) => {
+            return Err(GLueError::from(
+               GLueErrorKind::NoDevice,
+               &format!("no egl client extensions {e}"),
+            ));
+         }
+         Ok(s) => s.to_string_lossy().to_string(),
+      };
+      let has_ext = |ext: &str| client_exts.split_whitespace().any(|e| e == ext);
+
+      let mut devices = Vec::new();
+      let can_enumerate = has_ext(DEVICE_ENUMERATION_EXTENSION) || has_ext(DEVICE_BASE_EXTENSION);
+      if can_enumerate && has_ext(PLATFORM_DEVICE_EXTENSION) {
+         let query_devices: QueryDevicesEXT = match egl.get_proc_address("eglQueryDevicesEXT") {
+            None => {
+               return Err(GLueError::from(
+                  GLueErrorKind::NoDevice,
+                  "eglQueryDevicesEXT is missing",
+               ));
+            }
+            Some(f) => unsafe { std::mem::transmute::<extern "system" fn(), QueryDevicesEXT>(f) },
+         };
+         let query_string: Option<QueryDeviceStringEXT> =
+            match has_ext(DEVICE_QUERY_EXTENSION) || has_ext(DEVICE_BASE_EXTENSION) {
+               false => None,
+               true => egl
+                  .get_proc_address("eglQueryDeviceStringEXT")
+                  .map(|f| unsafe {
+                     std::mem::transmute::<extern "system" fn(), QueryDeviceStringEXT>(f)
+                  }),
+            };
+
+         let mut count: egl::Int = 0;
+         let mut ptrs = Vec::new();
+         unsafe {
+            if query_devices(0, std::ptr::null_mut(), &mut count) == egl::TRUE && count > 0 {
+               ptrs.resize(count as usize, std::ptr::null_mut());
+               if query_devices(count, ptrs.as_mut_ptr(), &mut count) != egl::TRUE {
+                  count = 0;
+               }
+            }
+         }
+         ptrs.truncate(count.max(0) as usize);
+
+         for (i, ptr) in ptrs.into_iter().enumerate() {
+            let query = |name: egl::Int| -> Option<String> {
+               let query_string = query_string?;
+               let s = unsafe { query_string(ptr, name) };
+               if s.is_null() {
+                  return None;
+               }
+               Some(unsafe { CStr::from_ptr(s) }.to_string_lossy().to_string())
+            };
+            let device_exts = query(egl::EXTENSIONS).unwrap_or_default();
+            let has_name_query = device_exts
+               .split_whitespace()
+               .any(|e| e == DEVICE_QUERY_NAME_EXTENSION);
+            let drm_path = query(DRM_DEVICE_FILE_EXT);
+            let (name, vendor) = match has_name_query {
+               true => (query(RENDERER_EXT), query(egl::VENDOR)),
+               false => (None, None),
+            };
+            let name = match (name, &drm_path) {
+               (Some(n), _) => n,
+               (None, Some(path)) => path.clone(),
+               (None, None) => format!("egl device {i}"),
+            };
+            devices.push(Device {
+               kind: DeviceKind::Hardware,
+               ptr: ptr as usize,
+               name,
+               vendor: vendor.unwrap_or("unknown".to_string()),
+               drm_path,
+            });
+         }
+      }
+      if has_ext(PLATFORM_SURFACELESS_EXTENSION) {
+         devices.push(Device {
+            kind: DeviceKind::Surfaceless,
+            ptr: 0,
+            name: "surfaceless".to_string(),
+            vendor: "mesa".to_string(),
+            drm_path: None,
+         });
+      }
+      Ok(devices)
+   }
+
+   pub(crate) fn display(&self) -> Result<egl::Display, GLueError> {
+      let egl = egl::Instance::new(egl::Static);
+      let display = unsafe {
+         match self.kind {
+            DeviceKind::Default => egl
+               .get_display(egl::DEFAULT_DISPLAY)
+               .ok_or(egl::Error::BadDisplay),
+            DeviceKind::Hardware => egl.get_platform_display(
+               PLATFORM_DEVICE_EXT,
+               self.ptr as egl::NativeDisplayType,
+               &[egl::ATTRIB_NONE],
+            ),
+            DeviceKind::Surfaceless => egl.get_platform_display(
+               PLATFORM_SURFACELESS_MESA,
+               egl::DEFAULT_DISPLAY,
+               &[egl::ATTRIB_NONE],
+            ),
+         }
+      };
+      match display {
+         Err(e) => Err(GLueError::from(
+            GLueErrorKind::NoDisplay,
+            &format!("no display found for {} {e}", self.name),
+         )),
+         Ok(d) => Ok(d),
+      }
+   }
+}
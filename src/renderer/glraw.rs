@@ -1,35 +1,93 @@
-use crate::{Cull, GLueError, GLueErrorKind, PolyMode, Size2D};
+use crate::renderer::device::SURFACELESS_CONTEXT_EXTENSION;
+use crate::renderer::{collect_garbage, forget_garbage};
+use crate::{
+   Device, GLApi, GLProfile, GLueError, GLueErrorKind, GpuConfig, ImgFormat, PolyMode, RenderState,
+   RenderTarget, Size2D, TargetConfig, load_spirv_fns,
+};
 
+use gl::types::GLenum;
 use khronos_egl as egl;
 use kolor::RGBA;
+use std::cell::Cell;
 
 pub(crate) const GL_SPV_EXTENSION: &str = "GL_ARB_gl_spirv";
 pub(crate) const SPIRV_EXTENSIONS: &str = "GL_ARB_spirv_extensions";
 
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Ctx {
+   pub(crate) api: GLApi,
+   pub(crate) major: u8,
+   pub(crate) minor: u8,
+   pub(crate) default_fbo: u32,
+   pub(crate) generation: u32,
+}
+
+thread_local! {
+   static CURRENT_CTX: Cell<Ctx> = const {
+      Cell::new(Ctx {
+         api: GLApi::GL,
+         major: 3,
+         minor: 3,
+         default_fbo: 0,
+         generation: 0,
+      })
+   };
+}
+
+impl Ctx {
+   pub(crate) fn current() -> Ctx {
+      CURRENT_CTX.with(|c| c.get())
+   }
+   fn make_current(self) {
+      CURRENT_CTX.with(|c| c.set(self))
+   }
+   fn set_default_fbo(id: u32) {
+      CURRENT_CTX.with(|c| {
+         let mut ctx = c.get();
+         ctx.default_fbo = id;
+         c.set(ctx)
+      })
+   }
+
+   pub(crate) fn is_es(&self) -> bool {
+      self.api == GLApi::ES
+   }
+   pub(crate) fn at_least(&self, major: u8, minor: u8) -> bool {
+      (self.major, self.minor) >= (major, minor)
+   }
+}
+
 pub struct GL {
    pub(crate) display: egl::Display,
+   pub(crate) config: egl::Config,
    pub(crate) context: egl::Context,
-   pub(crate) surface: egl::Surface,
+   pub(crate) surface: Option<egl::Surface>,
+   pub(crate) main: Option<RenderTarget>,
+   pub(crate) depth_fmt: ImgFormat,
+   pub(crate) samples: u32,
+   pub(crate) size: Size2D,
+   pub(crate) major: u8,
+   pub(crate) minor: u8,
    pub(crate) gl_ver: String,
    pub(crate) glsl_ver: String,
    pub(crate) device: String,
+   pub(crate) vendor: String,
+   pub(crate) generation: u32,
 }
 
 impl GL {
-   pub(crate) fn load(width: i32, height: i32) -> Result<GL, GLueError> {
+   pub(crate) fn load(cfg: &GpuConfig) -> Result<GL, GLueError> {
+      let size = cfg.size;
       let egl = egl::Instance::new(egl::Static);
 
-      // Get default display
-      let display = unsafe {
-         match egl.get_display(egl::DEFAULT_DISPLAY) {
-            None => {
-               return Err(GLueError::from(
-                  GLueErrorKind::NoDisplay,
-                  "no display found",
-               ));
-            }
-            Some(d) => d,
-         }
+      // Get display of the chosen device
+      let display = match &cfg.device {
+         None => Device::default_display().display(),
+         Some(device) => device.display(),
+      };
+      let display = match display {
+         Err(e) => return Err(e),
+         Ok(d) => d,
       };
 
       let _version = match egl.initialize(display) {
@@ -42,47 +100,36 @@ impl GL {
          Ok((v1, v2)) => (v1, v2),
       };
 
-      // Choose config
-      let attribs = [
-         egl::SURFACE_TYPE,
-         egl::PBUFFER_BIT,
-         egl::RENDERABLE_TYPE,
-         egl::OPENGL_BIT,
-         egl::RED_SIZE,
-         8,
-         egl::GREEN_SIZE,
-         8,
-         egl::BLUE_SIZE,
-         8,
-         egl::ALPHA_SIZE,
-         8,
-         egl::DEPTH_SIZE,
-         24,
-         egl::NONE,
-      ];
+      let surfaceless = match egl.query_string(Some(display), egl::EXTENSIONS) {
+         Err(_) => false,
+         Ok(exts) => exts
+            .to_string_lossy()
+            .split_whitespace()
+            .any(|e| e == SURFACELESS_CONTEXT_EXTENSION),
+      };
 
-      let mut configs = Vec::with_capacity(1);
-      match egl.choose_config(display, &attribs, &mut configs) {
+      // Choose config, without pbuffer support if the display can go surfaceless
+      let config = match choose_config(&egl, display, cfg, egl::PBUFFER_BIT) {
+         Ok(c) => Ok(c),
+         Err(_) if surfaceless => choose_config(&egl, display, cfg, 0),
+         Err(e) => Err(e),
+      };
+      let config = match config {
          Err(e) => {
-            return Err(GLueError::from(
-               GLueErrorKind::ConfigFailed,
-               &format!("opengl config failed {e}"),
-            ));
+            release_display(&egl, display, None);
+            return Err(e);
          }
-         Ok(_) => {}
-      }
-
-      if configs.is_empty() {
-         return Err(GLueError::from(
-            GLueErrorKind::ConfigFailed,
-            "opengl config is empty",
-         ));
-      }
-      let config = configs[0];
+         Ok(c) => c,
+      };
 
       // Bind OpenGL API
-      match egl.bind_api(egl::OPENGL_API) {
+      let api = match cfg.api {
+         GLApi::GL => egl::OPENGL_API,
+         GLApi::ES => egl::OPENGL_ES_API,
+      };
+      match egl.bind_api(api) {
          Err(e) => {
+            release_display(&egl, display, None);
             return Err(GLueError::from(
                GLueErrorKind::BindFailed,
                &format!("opengl bind failed {e}"),
@@ -91,32 +138,61 @@ impl GL {
          Ok(_) => {}
       }
 
-      // Create pbuffer surface
-      let pbuffer_attribs = [egl::WIDTH, width, egl::HEIGHT, height, egl::NONE];
-
-      let surface = match egl.create_pbuffer_surface(display, config, &pbuffer_attribs) {
+      // Create pbuffer surface, falling back to a surfaceless context
+      let surface = match create_pbuffer(&egl, display, config, size) {
+         Ok(s) => Some(s),
+         Err(_) if surfaceless => None,
          Err(e) => {
-            return Err(GLueError::from(
-               GLueErrorKind::MakeSurfaceFailed,
-               &format!("opengl surface creation failed {e}"),
-            ));
+            release_display(&egl, display, None);
+            return Err(e);
          }
-         Ok(s) => s,
       };
 
       // Create context
-      let context_attribs = [
+      let profile = match cfg.profile {
+         GLProfile::Core => egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+         GLProfile::Compat => egl::CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT,
+      };
+      let mut context_attribs = vec![
          egl::CONTEXT_MAJOR_VERSION,
-         3,
+         cfg.major as egl::Int,
          egl::CONTEXT_MINOR_VERSION,
-         3,
-         egl::CONTEXT_OPENGL_PROFILE_MASK,
-         egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
-         egl::NONE,
+         cfg.minor as egl::Int,
       ];
+      if cfg.api == GLApi::GL {
+         context_attribs.extend([egl::CONTEXT_OPENGL_PROFILE_MASK, profile]);
+      }
+      if cfg.debug {
+         context_attribs.extend([egl::CONTEXT_OPENGL_DEBUG, egl::TRUE as egl::Int]);
+      }
+      if cfg.robust {
+         context_attribs.extend([
+            egl::CONTEXT_OPENGL_ROBUST_ACCESS,
+            egl::TRUE as egl::Int,
+            egl::CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY,
+            egl::LOSE_CONTEXT_ON_RESET,
+         ]);
+      }
+      context_attribs.push(egl::NONE);
 
       let context = match egl.create_context(display, config, None, &context_attribs) {
+         Err(egl::Error::BadMatch) => {
+            release_display(&egl, display, surface);
+            // any of the version, profile, robustness or debug attributes can cause a mismatch
+            let mut what = format!("opengl {}.{} {:?}", cfg.major, cfg.minor, cfg.profile);
+            match (cfg.robust, cfg.debug) {
+               (false, false) => {}
+               (true, false) => what.push_str(" with robust access"),
+               (false, true) => what.push_str(" with debug output"),
+               (true, true) => what.push_str(" with robust access and debug output"),
+            }
+            return Err(GLueError::from(
+               GLueErrorKind::UnsupportedVersion,
+               &format!("{what} is unavailable"),
+            ));
+         }
          Err(e) => {
+            release_display(&egl, display, surface);
             return Err(GLueError::from(
                GLueErrorKind::MakeContextFailed,
                &format!("opengl context creation failed {e}"),
@@ -125,8 +201,45 @@ impl GL {
          Ok(c) => c,
       };
 
+      let depth_fmt = match cfg.stencil_bits > 0 {
+         true => ImgFormat::DepthStencil(24),
+         false => ImgFormat::Depth(cfg.depth_bits.max(16)),
+      };
+      // from here on a failed load is cleaned up by dropping gl
+      let prev_ctx = Ctx::current();
+      let mut gl = Self {
+         display,
+         config,
+         context,
+         surface,
+         main: None,
+         depth_fmt,
+         samples: cfg.samples as u32,
+         size,
+         major: 0,
+         minor: 0,
+         gl_ver: String::new(),
+         glsl_ver: String::new(),
+         device: String::new(),
+         vendor: String::new(),
+         generation: prev_ctx.generation + 1,
+      };
+      match gl.init(cfg) {
+         Ok(()) => Ok(gl),
+         Err(e) => {
+            drop(gl);
+            prev_ctx.make_current();
+            RenderState::reset_cache();
+            Err(e)
+         }
+      }
+   }
+
+   fn init(&mut self, cfg: &GpuConfig) -> Result<(), GLueError> {
+      let egl = egl::Instance::new(egl::Static);
+
       // Make context current
-      match egl.make_current(display, Some(surface), Some(surface), Some(context)) {
+      match egl.make_current(self.display, self.surface, self.surface, Some(self.context)) {
          Err(e) => {
             return Err(GLueError::from(
                GLueErrorKind::MakeCurrentFailed,
@@ -138,82 +251,247 @@ impl GL {
 
       // Load GL functions
       gl::load_with(|s| egl.get_proc_address(s).unwrap() as *const _);
+      load_spirv_fns(|s| match egl.get_proc_address(s) {
+         None => std::ptr::null(),
+         Some(f) => f as *const _,
+      });
 
       // Fetch GL info
-      let gl_ver = unsafe {
-         let ptr = gl::GetString(gl::VERSION);
-         if ptr.is_null() {
+      let (mut major, mut minor) = (0, 0);
+      unsafe {
+         gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+         gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+      }
+      self.major = major as u8;
+      self.minor = minor as u8;
+      Ctx {
+         api: cfg.api,
+         major: major as u8,
+         minor: minor as u8,
+         default_fbo: 0,
+         generation: self.generation,
+      }
+      .make_current();
+      RenderState::reset_cache();
+      if (major, minor) < (cfg.major as i32, cfg.minor as i32) {
+         return Err(GLueError::from(
+            GLueErrorKind::UnsupportedVersion,
+            &format!(
+               "opengl {}.{} requested but only {major}.{minor} is available",
+               cfg.major, cfg.minor
+            ),
+         ));
+      }
+
+      self.gl_ver = match gl_string(gl::VERSION) {
+         None => {
             return Err(GLueError::from(
                GLueErrorKind::NoVersion,
                "couldn't parse opengl version",
             ));
          }
-         let cstr = std::ffi::CStr::from_ptr(ptr as *const i8);
-         match cstr.to_str() {
-            Ok(s) => format!("OPENGL {s}"),
-            Err(e) => {
-               return Err(GLueError::wtf(&format!("c-string failed {e}")));
-            }
-         }
+         Some(Err(e)) => return Err(e),
+         Some(Ok(s)) => format!("OPENGL {s}"),
       };
-
-      let glsl_ver = unsafe {
-         let ptr = gl::GetString(gl::SHADING_LANGUAGE_VERSION);
-         if ptr.is_null() {
+      self.glsl_ver = match gl_string(gl::SHADING_LANGUAGE_VERSION) {
+         None => {
             return Err(GLueError::from(
                GLueErrorKind::NoVersion,
                "couldn't parse glsl version",
             ));
          }
-         let cstr = std::ffi::CStr::from_ptr(ptr as *const i8);
-         match cstr.to_str() {
-            Ok(s) => s.to_string(),
-            Err(e) => {
-               return Err(GLueError::wtf(&format!("c-string failed {e}")));
-            }
-         }
+         Some(Err(e)) => return Err(e),
+         Some(Ok(s)) => s,
       };
-
-      let device = unsafe {
-         let ptr = gl::GetString(gl::RENDERER);
-         if ptr.is_null() {
+      self.device = match gl_string(gl::RENDERER) {
+         None => {
             return Err(GLueError::from(
                GLueErrorKind::NoDevice,
                "couldn't parse device name",
             ));
          }
-         let cstr = std::ffi::CStr::from_ptr(ptr as *const i8);
-         match cstr.to_str() {
-            Ok(s) => s.to_string(),
-            Err(e) => {
-               return Err(GLueError::wtf(&format!("c-string failed {e}")));
-            }
+         Some(Err(e)) => return Err(e),
+         Some(Ok(s)) => s,
+      };
+      self.vendor = match gl_string(gl::VENDOR) {
+         None => {
+            return Err(GLueError::from(
+               GLueErrorKind::NoDevice,
+               "couldn't parse device vendor",
+            ));
          }
+         Some(Err(e)) => return Err(e),
+         Some(Ok(s)) => s,
       };
 
-      Ok(Self {
-         display,
-         context,
-         surface,
-         gl_ver,
-         glsl_ver,
-         device,
-      })
+      // Without a pbuffer everything renders into a main target instead
+      if self.surface.is_none() {
+         match self.make_main_target(self.size) {
+            Err(e) => return Err(e),
+            Ok(_) => {}
+         }
+      }
+      Ok(())
+   }
+
+   fn make_main_target(&mut self, size: Size2D) -> Result<(), GLueError> {
+      let main = TargetConfig::new(size)
+         .with_color(ImgFormat::RGBA(8))
+         .with_depth(self.depth_fmt)
+         .with_samples(self.samples)
+         .ship();
+      let main = match main {
+         Err(e) => return Err(e),
+         Ok(t) => t,
+      };
+      Ctx::set_default_fbo(main.draw_fbo_id());
+      main.bind();
+      match self.main.replace(main) {
+         None => {}
+         Some(old) => old.delete(),
+      }
+      Ok(())
+   }
+}
+
+// undoes egl initialize (and the surface) for a load that failed before a context existed
+fn release_display(
+   egl: &egl::Instance<egl::Static>,
+   display: egl::Display,
+   surface: Option<egl::Surface>,
+) {
+   match surface {
+      None => {}
+      Some(surface) => {
+         let _ = egl.destroy_surface(display, surface);
+      }
+   }
+   let _ = egl.terminate(display);
+}
+
+// None when the string is missing
+fn gl_string(name: GLenum) -> Option<Result<String, GLueError>> {
+   unsafe {
+      let ptr = gl::GetString(name);
+      if ptr.is_null() {
+         return None;
+      }
+      let cstr = std::ffi::CStr::from_ptr(ptr as *const i8);
+      match cstr.to_str() {
+         Ok(s) => Some(Ok(s.to_string())),
+         Err(e) => Some(Err(GLueError::wtf(&format!("c-string failed {e}")))),
+      }
+   }
+}
+
+fn choose_config(
+   egl: &egl::Instance<egl::Static>,
+   display: egl::Display,
+   cfg: &GpuConfig,
+   surface_type: egl::Int,
+) -> Result<egl::Config, GLueError> {
+   let mut attribs = vec![
+      egl::SURFACE_TYPE,
+      surface_type,
+      egl::RENDERABLE_TYPE,
+      match cfg.api {
+         GLApi::GL => egl::OPENGL_BIT,
+         GLApi::ES => egl::OPENGL_ES3_BIT,
+      },
+      egl::RED_SIZE,
+      8,
+      egl::GREEN_SIZE,
+      8,
+      egl::BLUE_SIZE,
+      8,
+      egl::ALPHA_SIZE,
+      8,
+      egl::DEPTH_SIZE,
+      cfg.depth_bits as egl::Int,
+      egl::STENCIL_SIZE,
+      cfg.stencil_bits as egl::Int,
+   ];
+   if cfg.samples > 0 {
+      attribs.extend([
+         egl::SAMPLE_BUFFERS,
+         1,
+         egl::SAMPLES,
+         cfg.samples as egl::Int,
+      ]);
+   }
+   attribs.push(egl::NONE);
+
+   let mut configs = Vec::with_capacity(1);
+   match egl.choose_config(display, &attribs, &mut configs) {
+      Err(e) => {
+         return Err(GLueError::from(
+            GLueErrorKind::ConfigFailed,
+            &format!("opengl config failed {e}"),
+         ));
+      }
+      Ok(_) => {}
+   }
+
+   if configs.is_empty() {
+      return Err(GLueError::from(
+         GLueErrorKind::ConfigFailed,
+         "opengl config is empty",
+      ));
+   }
+   Ok(configs[0])
+}
+
+fn create_pbuffer(
+   egl: &egl::Instance<egl::Static>,
+   display: egl::Display,
+   config: egl::Config,
+   size: Size2D,
+) -> Result<egl::Surface, GLueError> {
+   let pbuffer_attribs = [
+      egl::WIDTH,
+      size.w as egl::Int,
+      egl::HEIGHT,
+      size.h as egl::Int,
+      egl::NONE,
+   ];
+
+   match egl.create_pbuffer_surface(display, config, &pbuffer_attribs) {
+      Err(e) => Err(GLueError::from(
+         GLueErrorKind::MakeSurfaceFailed,
+         &format!("opengl surface creation failed {e}"),
+      )),
+      Ok(s) => Ok(s),
    }
 }
 
 impl Drop for GL {
    fn drop(&mut self) {
+      match self.main.take() {
+         None => {}
+         Some(main) => main.delete(),
+      }
+      // a load that failed before its context became current has nothing to collect
+      if Ctx::current().generation == self.generation {
+         collect_garbage();
+      }
       let egl = egl::Instance::new(egl::Static);
       let _ = egl.make_current(self.display, None, None, None);
       let _ = egl.destroy_context(self.display, self.context);
-      let _ = egl.destroy_surface(self.display, self.surface);
+      // whatever is still queued for this context died with it
+      forget_garbage(self.generation);
+      match self.surface {
+         None => {}
+         Some(surface) => {
+            let _ = egl.destroy_surface(self.display, surface);
+         }
+      }
       let _ = egl.terminate(self.display);
    }
 }
 
 impl GL {
    pub(crate) fn clear(&self) {
+      RenderState::for_clear().apply();
       unsafe {
          gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
       }
@@ -224,12 +502,60 @@ impl GL {
          gl::ClearColor(color.r(), color.g(), color.b(), color.a());
       }
    }
-   pub(crate) fn resize(&self, size: Size2D) {
+   pub(crate) fn resize(&mut self, size: Size2D) -> Result<(), GLueError> {
+      if size.w == 0 || size.h == 0 {
+         return Err(GLueError::from(
+            GLueErrorKind::MakeSurfaceFailed,
+            &format!("invalid surface size {}x{}", size.w, size.h),
+         ));
+      }
+      let old_surface = match self.surface {
+         None => {
+            match self.make_main_target(size) {
+               Err(e) => return Err(e),
+               Ok(_) => {}
+            }
+            self.size = size;
+            self.set_viewport(size);
+            return Ok(());
+         }
+         Some(s) => s,
+      };
+      let egl = egl::Instance::new(egl::Static);
+      let surface = match create_pbuffer(&egl, self.display, self.config, size) {
+         Err(e) => return Err(e),
+         Ok(s) => s,
+      };
+      match egl.make_current(
+         self.display,
+         Some(surface),
+         Some(surface),
+         Some(self.context),
+      ) {
+         Err(e) => {
+            let _ = egl.destroy_surface(self.display, surface);
+            return Err(GLueError::from(
+               GLueErrorKind::MakeCurrentFailed,
+               &format!("opengl context current failed {e}"),
+            ));
+         }
+         Ok(_) => {}
+      }
+      let _ = egl.destroy_surface(self.display, old_surface);
+      self.surface = Some(surface);
+      self.size = size;
+      self.set_viewport(size);
+      Ok(())
+   }
+   pub(crate) fn set_viewport(&self, size: Size2D) {
       unsafe {
          gl::Viewport(0, 0, size.w as i32, size.h as i32);
       }
    }
    pub(crate) fn poly_mode(&self, mode: PolyMode) {
+      if Ctx::current().is_es() {
+         return;
+      }
       unsafe {
          match mode {
             PolyMode::WireFrame => gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE),
@@ -242,6 +568,9 @@ impl GL {
       }
    }
    pub(crate) fn enable_msaa(&self, enable: bool) {
+      if Ctx::current().is_es() {
+         return;
+      }
       unsafe {
          match enable {
             true => gl::Enable(gl::MULTISAMPLE),
@@ -249,45 +578,10 @@ impl GL {
          }
       }
    }
-   pub(crate) fn enable_depth(&self, enable: bool) {
-      unsafe {
-         match enable {
-            true => gl::Enable(gl::DEPTH_TEST),
-            false => gl::Disable(gl::DEPTH_TEST),
-         }
-      }
-   }
-   pub(crate) fn enable_alpha(&self, enable: bool) {
-      unsafe {
-         match enable {
-            true => {
-               gl::Enable(gl::BLEND);
-               gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-            }
-            false => gl::Disable(gl::BLEND),
-         }
-      }
-   }
-   pub(crate) fn enable_cull(&self, enable: bool) {
-      unsafe {
-         match enable {
-            true => {
-               gl::Enable(gl::CULL_FACE);
-               gl::CullFace(gl::BACK);
-            }
-            false => gl::Disable(gl::CULL_FACE),
-         }
-      }
-   }
-   pub(crate) fn set_cull_face(&self, face: Cull) {
-      unsafe {
-         match face {
-            Cull::Clock => gl::FrontFace(gl::CW),
-            Cull::AntiClock => gl::FrontFace(gl::CCW),
-         }
-      }
-   }
    pub(crate) fn set_point_size(&self, size: f32) {
+      if Ctx::current().is_es() {
+         return;
+      }
       unsafe {
          gl::PointSize(size);
       }
@@ -1,9 +1,14 @@
 use crate::asset::ATTRInfo;
-use crate::{ATTRType, Transform2D};
+use crate::renderer::{GlObject, ObjectKind, copy_buffer, create_read_buffer};
+use crate::{
+   ATTRType, Camera, GLueError, GLueErrorKind, GpuPod, PendingRead, RenderState, Transform2D,
+};
 use crate::{Shader, Transform3D};
+use cgmath::ortho;
 use gl::types::{GLenum, GLint, GLsizei, GLsizeiptr};
 use std::ffi::c_void;
 use std::ptr;
+use std::rc::Rc;
 
 #[derive(Clone, Debug, Copy)]
 pub enum DrawMode {
@@ -11,6 +16,7 @@ pub enum DrawMode {
    Lines,
    Triangles,
    Strip,
+   Patches(u32),
 }
 
 impl Default for DrawMode {
@@ -19,16 +25,15 @@ impl Default for DrawMode {
    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub(crate) struct MeshHandle {
    pub(crate) layouts: Vec<(ATTRInfo, u32)>,
-   pub(crate) draw_mode: DrawMode,
    pub(crate) has_indices: bool,
    pub(crate) vert_count: u32,
    pub(crate) ind_count: u32,
-   pub(crate) vao_id: u32,
-   pub(crate) buf_id: u32,
-   pub(crate) ind_id: u32,
+   pub(crate) vao: GlObject,
+   pub(crate) buf: GlObject,
+   pub(crate) ind: GlObject,
 }
 
 macro_rules! mesh_struct {
@@ -36,8 +41,11 @@ macro_rules! mesh_struct {
       #[derive(Clone, Debug)]
       pub struct $mesh {
          pub(crate) visibility: bool,
-         pub(crate) handle: MeshHandle,
+         pub(crate) handle: Rc<MeshHandle>,
+         pub(crate) draw_mode: DrawMode,
          pub(crate) shader: Option<Shader>,
+         pub(crate) label: Option<String>,
+         pub(crate) state: Option<RenderState>,
          pub transform: $transform,
       }
 
@@ -49,10 +57,28 @@ macro_rules! mesh_struct {
             self.shader = None
          }
          pub fn get_draw_mode(&self) -> DrawMode {
-            self.handle.draw_mode
+            self.draw_mode
          }
          pub fn set_draw_mode(&mut self, draw_mode: DrawMode) {
-            self.handle.draw_mode = draw_mode
+            self.draw_mode = draw_mode
+         }
+
+         pub fn render_state(&self) -> Option<RenderState> {
+            self.state
+         }
+         pub fn set_render_state(&mut self, state: RenderState) {
+            self.state = Some(state)
+         }
+         pub fn remove_render_state(&mut self) {
+            self.state = None
+         }
+
+         pub fn label(&self) -> Option<&str> {
+            self.label.as_deref()
+         }
+         pub fn set_label(&mut self, label: &str) {
+            self.handle.set_label(label);
+            self.label = Some(label.to_string());
          }
 
          pub fn index_count(&self) -> u32 {
@@ -88,77 +114,125 @@ macro_rules! mesh_struct {
 mesh_struct!(Mesh3D, Transform3D);
 mesh_struct!(Mesh2D, Transform2D);
 
+pub trait Drawable {
+   fn render_with(&self, cam: &Camera) -> Result<(), GLueError>;
+}
+
+impl Drawable for Mesh3D {
+   fn render_with(&self, cam: &Camera) -> Result<(), GLueError> {
+      self.render_cam(Some(cam))
+   }
+}
+
+impl Drawable for Mesh2D {
+   fn render_with(&self, cam: &Camera) -> Result<(), GLueError> {
+      self.render_cam(Some(cam))
+   }
+}
+
 impl Mesh3D {
-   pub fn render(&self) {
+   pub fn render(&self) -> Result<(), GLueError> {
+      self.render_cam(None)
+   }
+   fn render_cam(&self, cam: Option<&Camera>) -> Result<(), GLueError> {
       if !self.is_visible() {
-         return;
+         return Ok(());
       }
       let shader = match &self.shader {
-         None => return,
+         None => return Ok(()),
          Some(sh) => sh,
       };
+      self.state.unwrap_or(RenderState::base()).apply();
       shader.bind();
-      //shader.set_uni_m4_f32("uView", self.cam.transform.view_matrix());
-      //shader.set_uni_m4_f32(s, "uProj", self.cam.transform.proj_matrix());
+      match cam {
+         None => {}
+         Some(cam) => {
+            shader.try_set_uni_m4_f32("uView", cam.transform.view_matrix());
+            shader.try_set_uni_m4_f32("uProj", cam.transform.proj_matrix());
+         }
+      }
 
       let tfm = self.transform.matrix();
-      shader.set_uni_m4_f32("uTfm", tfm);
+      match shader.set_uni_m4_f32("uTfm", tfm) {
+         Err(e) => return Err(e),
+         Ok(()) => {}
+      }
 
       shader.bind_textures();
       shader.bind_storages();
-      self.handle.draw()
+      self.handle.draw(&self.draw_mode);
+      Ok(())
    }
    pub fn delete(self) {
-      self.handle.delete()
+      drop(self)
    }
 }
 
 impl Mesh2D {
-   pub fn render(&self) {
+   pub fn render(&self) -> Result<(), GLueError> {
+      self.render_cam(None)
+   }
+   fn render_cam(&self, cam: Option<&Camera>) -> Result<(), GLueError> {
       if !self.is_visible() {
-         return;
+         return Ok(());
       }
       let shader = match &self.shader {
-         None => return,
+         None => return Ok(()),
          Some(sh) => sh,
       };
+      self.state.unwrap_or(RenderState::base()).apply();
       shader.bind();
 
-      let _scale = 1.0;
-      let _max_layers = 255;
+      let scale = 1.0;
+      let max_layers = 255;
       let tfm = self.transform.matrix();
       let layer = self.transform.layer() as u32;
-      //let w = self.cam.transform.size.aspect_ratio() * scale;
-      //let proj = ortho(-w, w, -scale, scale, 0.0, -(max_layers + 1) as f32);
-
-      //shader.set_uni_m4_f32("uProj", proj);
-      shader.set_uni_m4_f32("uTfm", tfm);
-      shader.set_uni_u32("uLayer", layer);
+      match cam {
+         None => {}
+         Some(cam) => {
+            let w = cam.transform.size.aspect_ratio() * scale;
+            let proj = ortho(-w, w, -scale, scale, 0.0, -(max_layers + 1) as f32);
+            shader.try_set_uni_m4_f32("uProj", proj);
+         }
+      }
+      match shader.set_uni_m4_f32("uTfm", tfm) {
+         Err(e) => return Err(e),
+         Ok(()) => {}
+      }
+      match shader.set_uni_u32("uLayer", layer) {
+         Err(e) => return Err(e),
+         Ok(()) => {}
+      }
 
       shader.bind_textures();
       shader.bind_storages();
-      self.handle.draw()
+      self.handle.draw(&self.draw_mode);
+      Ok(())
    }
 
    pub fn delete(self) {
-      self.handle.delete()
+      drop(self)
    }
 }
 
 impl MeshHandle {
-   pub(crate) fn draw(&self) {
-      bind_layouts(self.vao_id);
+   pub(crate) fn draw(&self, draw_mode: &DrawMode) {
+      bind_layouts(self.vao.id);
+      match draw_mode {
+         DrawMode::Patches(n) => set_patch_vertices(*n),
+         _ => {}
+      }
       match self.has_indices {
-         false => self.draw_array(),
+         false => self.draw_array(draw_mode),
          true => {
-            bind_index_buffer(self.ind_id);
-            self.draw_indexed();
+            bind_index_buffer(self.ind.id);
+            self.draw_indexed(draw_mode);
          }
       }
    }
 
-   pub(crate) fn draw_indexed(&self) {
-      let draw_mode = match_draw_mode(&self.draw_mode);
+   pub(crate) fn draw_indexed(&self, draw_mode: &DrawMode) {
+      let draw_mode = match_draw_mode(draw_mode);
       unsafe {
          gl::DrawElements(
             draw_mode,
@@ -169,16 +243,19 @@ impl MeshHandle {
       }
    }
 
-   pub(crate) fn draw_array(&self) {
-      let draw_mode = match_draw_mode(&self.draw_mode);
+   pub(crate) fn draw_array(&self, draw_mode: &DrawMode) {
+      let draw_mode = match_draw_mode(draw_mode);
       unsafe {
          gl::DrawArrays(draw_mode, 0, self.vert_count as GLsizei);
       }
    }
 
-   pub(crate) fn delete(self) {
-      delete_mesh_buffer(self.vao_id, self.buf_id);
-      delete_index_buffer(self.ind_id);
+   pub(crate) fn set_label(&self, label: &str) {
+      self.vao.label(label);
+      self.buf.label(&format!("{label} vertices"));
+      if self.has_indices {
+         self.ind.label(&format!("{label} indices"));
+      }
    }
 }
 
@@ -188,6 +265,13 @@ fn match_draw_mode(dm: &DrawMode) -> GLenum {
       DrawMode::Lines => gl::LINES,
       DrawMode::Triangles => gl::TRIANGLES,
       DrawMode::Strip => gl::TRIANGLE_STRIP,
+      DrawMode::Patches(_) => gl::PATCHES,
+   }
+}
+
+fn set_patch_vertices(n: u32) {
+   unsafe {
+      gl::PatchParameteri(gl::PATCH_VERTICES, n as GLint);
    }
 }
 
@@ -201,10 +285,15 @@ pub(crate) fn create_mesh_buffer() -> (u32, u32) {
    (v_id, b_id)
 }
 
-pub(crate) fn delete_mesh_buffer(v_id: u32, b_id: u32) {
+pub(crate) fn delete_vertex_array(id: u32) {
    unsafe {
-      gl::DeleteVertexArrays(1, &v_id);
-      gl::DeleteBuffers(1, &b_id);
+      gl::DeleteVertexArrays(1, &id);
+   }
+}
+
+pub(crate) fn delete_buffer(id: u32) {
+   unsafe {
+      gl::DeleteBuffers(1, &id);
    }
 }
 
@@ -321,36 +410,32 @@ pub(crate) fn unbind_index_buffer() {
    }
 }
 
-pub(crate) fn delete_index_buffer(id: u32) {
-   unsafe {
-      gl::DeleteBuffers(1, &id);
-   }
-}
-
 //SBO
 pub struct StorageBuffer<T> {
-   pub(crate) id: u32,
+   pub(crate) obj: Rc<GlObject>,
    pub(crate) count: usize,
+   pub(crate) label: Option<String>,
    _phantom: std::marker::PhantomData<T>,
 }
 
 impl<T> StorageBuffer<T> {
    pub fn id(&self) -> u32 {
-      self.id
+      self.obj.id
    }
 }
 
-impl<T> StorageBuffer<T> {
+impl<T: GpuPod> StorageBuffer<T> {
    pub(crate) fn bind(&self) {
-      bind_storage_buffer(self.id);
+      bind_storage_buffer(self.id());
    }
 
    pub fn new(count: usize) -> Self {
       let id = create_storage_buffer();
       resize_storage_buffer::<T>(id, count);
       StorageBuffer {
-         id,
+         obj: Rc::new(GlObject::new(ObjectKind::Buffer, id)),
          count,
+         label: None,
          _phantom: std::marker::PhantomData,
       }
    }
@@ -359,7 +444,7 @@ impl<T> StorageBuffer<T> {
       self.bind();
       if count != self.count {
          self.count = count;
-         resize_storage_buffer::<T>(self.id, self.count);
+         resize_storage_buffer::<T>(self.id(), self.count);
       }
    }
 
@@ -367,26 +452,39 @@ impl<T> StorageBuffer<T> {
       self.bind();
       let count = data.len();
       self.resize(count);
-      fill_storage_buffer(self.id, data)
+      fill_storage_buffer(self.id(), data)
    }
 
    pub fn subfill(&mut self, offset: usize, data: &[T]) {
       self.bind();
       let total_count = data.len() + offset;
       self.resize(total_count);
-      subfill_storage_buffer(self.id, offset, data)
+      subfill_storage_buffer(self.id(), offset, data)
    }
 
-   pub fn fetch(&self) -> Vec<T>
+   pub fn fetch(&self) -> Result<Vec<T>, GLueError>
    where
       T: Default + Clone,
    {
       self.bind();
-      read_storage_buffer(self.id, self.count)
+      read_storage_buffer(self.id(), self.count)
+   }
+
+   pub fn fetch_async(&self) -> PendingRead<T>
+   where
+      T: Default + Clone,
+   {
+      let size = self.byte_size();
+      unsafe {
+         gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+      }
+      let staging = create_read_buffer(gl::COPY_WRITE_BUFFER, size);
+      copy_buffer(self.id(), staging, size);
+      PendingRead::from_buffer(staging, self.count)
    }
 
    pub fn delete(self) {
-      delete_storage_buffer(self.id);
+      drop(self);
       unbind_storage_buffer()
    }
 
@@ -394,6 +492,14 @@ impl<T> StorageBuffer<T> {
       self.count
    }
 
+   pub fn label(&self) -> Option<&str> {
+      self.label.as_deref()
+   }
+   pub fn set_label(&mut self, label: &str) {
+      self.obj.label(label);
+      self.label = Some(label.to_string());
+   }
+
    pub fn byte_size(&self) -> usize {
       self.count * size_of::<T>()
    }
@@ -455,20 +561,31 @@ pub(crate) fn resize_storage_buffer<T>(id: u32, count: usize) {
    }
 }
 
-pub(crate) fn read_storage_buffer<T>(id: u32, count: usize) -> Vec<T>
+pub(crate) fn read_storage_buffer<T>(id: u32, count: usize) -> Result<Vec<T>, GLueError>
 where
    T: Default + Clone,
 {
+   let mut data = vec![T::default(); count];
+   if count == 0 {
+      return Ok(data);
+   }
    unsafe {
       bind_storage_buffer(id);
-      let mut data = vec![T::default(); count];
-      gl::GetBufferSubData(
-         gl::SHADER_STORAGE_BUFFER,
-         0,
-         (count * size_of::<T>()) as GLsizeiptr,
-         data.as_mut_ptr() as *mut c_void,
+      let size = (count * size_of::<T>()) as GLsizeiptr;
+      let mapped = gl::MapBufferRange(gl::SHADER_STORAGE_BUFFER, 0, size, gl::MAP_READ_BIT);
+      if mapped.is_null() {
+         return Err(GLueError::from(
+            GLueErrorKind::BindFailed,
+            &format!("could not map storage buffer {id} for reading"),
+         ));
+      }
+      ptr::copy_nonoverlapping(
+         mapped as *const u8,
+         data.as_mut_ptr() as *mut u8,
+         size as usize,
       );
-      data
+      gl::UnmapBuffer(gl::SHADER_STORAGE_BUFFER);
+      Ok(data)
    }
 }
 
@@ -478,12 +595,6 @@ pub(crate) fn unbind_storage_buffer() {
    }
 }
 
-pub(crate) fn delete_storage_buffer(id: u32) {
-   unsafe {
-      gl::DeleteBuffers(1, &id);
-   }
-}
-
 fn match_attr_type(attr_type: &ATTRType) -> GLenum {
    match attr_type {
       ATTRType::I8 => gl::BYTE,
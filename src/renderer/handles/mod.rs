@@ -1,7 +1,19 @@
 mod mesh;
+mod object;
+mod reflect;
 mod shader;
+mod sync;
+mod target;
 mod texture;
+mod ubo;
+mod uniform;
 
 pub use mesh::*;
+pub use object::*;
+pub use reflect::*;
 pub use shader::*;
+pub use sync::*;
+pub use target::*;
 pub use texture::*;
+pub use ubo::*;
+pub use uniform::*;
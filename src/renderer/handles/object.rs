@@ -0,0 +1,210 @@
+use crate::asset::{delete_program, delete_texture2d};
+use crate::renderer::glraw::Ctx;
+use crate::renderer::{
+   delete_buffer, delete_framebuffer, delete_query, delete_renderbuffer, delete_vertex_array,
+   label_object,
+};
+use gl::types::{GLenum, GLsync};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ObjectKind {
+   Program,
+   Texture,
+   Buffer,
+   VertexArray,
+   Framebuffer,
+   Renderbuffer,
+   Query,
+}
+
+impl ObjectKind {
+   pub fn as_str(&self) -> &str {
+      match self {
+         ObjectKind::Program => "program",
+         ObjectKind::Texture => "texture",
+         ObjectKind::Buffer => "buffer",
+         ObjectKind::VertexArray => "vertex array",
+         ObjectKind::Framebuffer => "framebuffer",
+         ObjectKind::Renderbuffer => "renderbuffer",
+         ObjectKind::Query => "query",
+      }
+   }
+   fn identifier(&self) -> GLenum {
+      match self {
+         ObjectKind::Program => gl::PROGRAM,
+         ObjectKind::Texture => gl::TEXTURE,
+         ObjectKind::Buffer => gl::BUFFER,
+         ObjectKind::VertexArray => gl::VERTEX_ARRAY,
+         ObjectKind::Framebuffer => gl::FRAMEBUFFER,
+         ObjectKind::Renderbuffer => gl::RENDERBUFFER,
+         ObjectKind::Query => gl::QUERY,
+      }
+   }
+}
+
+#[derive(Clone, Debug)]
+pub struct LiveObject {
+   pub kind: ObjectKind,
+   pub id: u32,
+   pub name: Option<String>,
+}
+
+thread_local! {
+   static NEXT_SERIAL: Cell<u64> = const { Cell::new(1) };
+   static GARBAGE: RefCell<Vec<(ObjectKind, u32, u32)>> = const { RefCell::new(Vec::new()) };
+   static SYNC_GARBAGE: RefCell<Vec<(GLsync, u32)>> = const { RefCell::new(Vec::new()) };
+   static LIVE_OBJECTS: RefCell<Option<BTreeMap<u64, LiveObject>>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug)]
+pub(crate) struct GlObject {
+   pub(crate) kind: ObjectKind,
+   pub(crate) id: u32,
+   generation: u32,
+   serial: u64,
+}
+
+impl GlObject {
+   pub(crate) fn new(kind: ObjectKind, id: u32) -> GlObject {
+      // headless jobs may never reach end_frame, so creating an object also frees the queue
+      collect_garbage();
+      let serial = NEXT_SERIAL.with(|s| {
+         let serial = s.get();
+         s.set(serial + 1);
+         serial
+      });
+      if id != 0 {
+         LIVE_OBJECTS.with(|l| match l.borrow_mut().as_mut() {
+            None => {}
+            Some(live) => {
+               live.insert(
+                  serial,
+                  LiveObject {
+                     kind,
+                     id,
+                     name: None,
+                  },
+               );
+            }
+         });
+      }
+      GlObject {
+         kind,
+         id,
+         generation: Ctx::current().generation,
+         serial,
+      }
+   }
+   pub(crate) fn shared(kind: ObjectKind, id: u32) -> Rc<GlObject> {
+      Rc::new(GlObject::new(kind, id))
+   }
+
+   pub(crate) fn label(&self, name: &str) {
+      label_object(self.kind.identifier(), self.id, name);
+      self.set_name(name);
+   }
+   fn set_name(&self, name: &str) {
+      LIVE_OBJECTS.with(|l| match l.borrow_mut().as_mut() {
+         None => {}
+         Some(live) => match live.get_mut(&self.serial) {
+            None => {}
+            Some(obj) => obj.name = Some(name.to_string()),
+         },
+      });
+   }
+}
+
+impl Drop for GlObject {
+   fn drop(&mut self) {
+      if self.id == 0 {
+         return;
+      }
+      LIVE_OBJECTS.with(|l| match l.borrow_mut().as_mut() {
+         None => {}
+         Some(live) => {
+            live.remove(&self.serial);
+         }
+      });
+      GARBAGE.with(|g| g.borrow_mut().push((self.kind, self.id, self.generation)));
+   }
+}
+
+// only objects of the current context can be deleted, the rest wait for theirs
+pub(crate) fn collect_garbage() -> usize {
+   let generation = Ctx::current().generation;
+   let garbage: Vec<_> = GARBAGE.with(|g| {
+      let mut g = g.borrow_mut();
+      let (current, other) = g
+         .drain(..)
+         .partition(|(_, _, obj_generation)| *obj_generation == generation);
+      *g = other;
+      current
+   });
+   let mut freed = 0;
+   for (kind, id, _) in garbage {
+      match kind {
+         ObjectKind::Program => delete_program(id),
+         ObjectKind::Texture => delete_texture2d(id),
+         ObjectKind::Buffer => delete_buffer(id),
+         ObjectKind::VertexArray => delete_vertex_array(id),
+         ObjectKind::Framebuffer => delete_framebuffer(id),
+         ObjectKind::Renderbuffer => delete_renderbuffer(id),
+         ObjectKind::Query => delete_query(id),
+      }
+      freed += 1;
+   }
+   let syncs: Vec<_> = SYNC_GARBAGE.with(|g| {
+      let mut g = g.borrow_mut();
+      let (current, other) = g
+         .drain(..)
+         .partition(|(_, obj_generation)| *obj_generation == generation);
+      *g = other;
+      current
+   });
+   for (sync, _) in syncs {
+      unsafe { gl::DeleteSync(sync) }
+      freed += 1;
+   }
+   freed
+}
+
+// syncs are pointers rather than names so they get their own queue
+pub(crate) fn queue_sync(sync: GLsync, generation: u32) {
+   SYNC_GARBAGE.with(|g| g.borrow_mut().push((sync, generation)))
+}
+
+pub(crate) fn forget_garbage(generation: u32) {
+   GARBAGE.with(|g| {
+      g.borrow_mut()
+         .retain(|(_, _, obj_generation)| *obj_generation != generation)
+   });
+   SYNC_GARBAGE.with(|g| {
+      g.borrow_mut()
+         .retain(|(_, obj_generation)| *obj_generation != generation)
+   });
+}
+
+pub(crate) fn track_objects(enable: bool) {
+   LIVE_OBJECTS.with(|l| {
+      let mut live = l.borrow_mut();
+      match (enable, live.is_some()) {
+         (true, false) => *live = Some(BTreeMap::new()),
+         (false, _) => *live = None,
+         _ => {}
+      }
+   })
+}
+
+pub(crate) fn is_tracking_objects() -> bool {
+   LIVE_OBJECTS.with(|l| l.borrow().is_some())
+}
+
+pub(crate) fn live_objects() -> Vec<LiveObject> {
+   LIVE_OBJECTS.with(|l| match l.borrow().as_ref() {
+      None => Vec::new(),
+      Some(live) => live.values().cloned().collect(),
+   })
+}
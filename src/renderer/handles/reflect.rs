@@ -0,0 +1,436 @@
+use crate::renderer::glraw::Ctx;
+use crate::{GLueError, GLueErrorKind, GpuPod, Shader, Slot, StorageBuffer};
+use gl::types::{GLchar, GLenum, GLint, GLsizei};
+use std::ffi::CString;
+use std::ptr;
+
+#[derive(Clone, Debug)]
+pub struct UniformInfo {
+   pub name: String,
+   pub gl_type: GLenum,
+   pub array_size: u32,
+   pub location: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AttributeInfo {
+   pub name: String,
+   pub gl_type: GLenum,
+   pub array_size: u32,
+   pub location: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockMember {
+   pub name: String,
+   pub gl_type: GLenum,
+   pub offset: u32,
+   pub array_size: u32,
+   pub array_stride: u32,
+   pub matrix_stride: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockInfo {
+   pub name: String,
+   pub binding: u32,
+   pub size: u32,
+   pub members: Vec<BlockMember>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+   pub uniforms: Vec<UniformInfo>,
+   pub attributes: Vec<AttributeInfo>,
+   pub uniform_blocks: Vec<BlockInfo>,
+   pub storage_blocks: Vec<BlockInfo>,
+   pub local_size: Option<[u32; 3]>,
+}
+
+impl UniformInfo {
+   pub fn type_name(&self) -> &str {
+      glsl_type_name(self.gl_type)
+   }
+}
+impl AttributeInfo {
+   pub fn type_name(&self) -> &str {
+      glsl_type_name(self.gl_type)
+   }
+}
+impl BlockMember {
+   pub fn type_name(&self) -> &str {
+      glsl_type_name(self.gl_type)
+   }
+}
+impl BlockInfo {
+   pub fn member(&self, name: &str) -> Option<&BlockMember> {
+      self.members.iter().find(|m| m.name == name)
+   }
+}
+
+impl ShaderReflection {
+   pub fn uniform(&self, name: &str) -> Option<&UniformInfo> {
+      self.uniforms.iter().find(|u| u.name == name)
+   }
+   pub fn attribute(&self, name: &str) -> Option<&AttributeInfo> {
+      self.attributes.iter().find(|a| a.name == name)
+   }
+   pub fn uniform_block(&self, name: &str) -> Option<&BlockInfo> {
+      self.uniform_blocks.iter().find(|b| b.name == name)
+   }
+   pub fn storage_block(&self, name: &str) -> Option<&BlockInfo> {
+      self.storage_blocks.iter().find(|b| b.name == name)
+   }
+}
+
+impl Shader {
+   pub fn reflect(&self) -> Result<ShaderReflection, GLueError> {
+      match check_program_interface() {
+         Err(e) => return Err(e),
+         Ok(()) => {}
+      }
+      let program = self.id();
+
+      // block members are listed under their block, not here
+      let mut uniforms = Vec::new();
+      for i in 0..resource_count(program, gl::UNIFORM) {
+         let props = resource_props(
+            program,
+            gl::UNIFORM,
+            i,
+            &[gl::TYPE, gl::ARRAY_SIZE, gl::LOCATION, gl::BLOCK_INDEX],
+         );
+         if props[3] != -1 {
+            continue;
+         }
+         uniforms.push(UniformInfo {
+            name: resource_name(program, gl::UNIFORM, i),
+            gl_type: props[0] as GLenum,
+            array_size: props[1].max(0) as u32,
+            location: as_location(props[2]),
+         });
+      }
+
+      let mut attributes = Vec::new();
+      if !self.is_compute {
+         for i in 0..resource_count(program, gl::PROGRAM_INPUT) {
+            let props = resource_props(
+               program,
+               gl::PROGRAM_INPUT,
+               i,
+               &[gl::TYPE, gl::ARRAY_SIZE, gl::LOCATION],
+            );
+            attributes.push(AttributeInfo {
+               name: resource_name(program, gl::PROGRAM_INPUT, i),
+               gl_type: props[0] as GLenum,
+               array_size: props[1].max(0) as u32,
+               location: as_location(props[2]),
+            });
+         }
+      }
+
+      let local_size = match self.is_compute {
+         false => None,
+         true => {
+            let mut size: [GLint; 3] = [0; 3];
+            unsafe {
+               gl::GetProgramiv(program, gl::COMPUTE_WORK_GROUP_SIZE, size.as_mut_ptr());
+            }
+            Some(size.map(|s| s.max(0) as u32))
+         }
+      };
+
+      Ok(ShaderReflection {
+         uniforms,
+         attributes,
+         uniform_blocks: query_blocks(program, gl::UNIFORM_BLOCK, gl::UNIFORM),
+         storage_blocks: query_blocks(program, gl::SHADER_STORAGE_BLOCK, gl::BUFFER_VARIABLE),
+         local_size,
+      })
+   }
+}
+
+impl Shader {
+   // binds at the block's own binding after checking the element stride against size_of::<T>()
+   pub fn set_storage_buffer<T: GpuPod>(
+      &mut self,
+      name: &str,
+      sbo: &StorageBuffer<T>,
+   ) -> Result<(), GLueError> {
+      match check_program_interface() {
+         Err(e) => return Err(e),
+         Ok(()) => {}
+      }
+      let program = self.id();
+      let index = match CString::new(name) {
+         Err(_) => gl::INVALID_INDEX,
+         Ok(c_name) => unsafe {
+            gl::GetProgramResourceIndex(program, gl::SHADER_STORAGE_BLOCK, c_name.as_ptr())
+         },
+      };
+      if index == gl::INVALID_INDEX {
+         return self.missing_block("storage block", name);
+      }
+
+      let props = resource_props(
+         program,
+         gl::SHADER_STORAGE_BLOCK,
+         index,
+         &[
+            gl::BUFFER_BINDING,
+            gl::BUFFER_DATA_SIZE,
+            gl::NUM_ACTIVE_VARIABLES,
+         ],
+      );
+      let (binding, block_size) = (props[0].max(0) as usize, props[1].max(0) as usize);
+      // a runtime sized array is checked by its stride, a lone array member too, anything else by block size
+      let count = props[2].max(0) as usize;
+      let mut vars = vec![0 as GLint; count];
+      if count > 0 {
+         let prop = gl::ACTIVE_VARIABLES;
+         unsafe {
+            gl::GetProgramResourceiv(
+               program,
+               gl::SHADER_STORAGE_BLOCK,
+               index,
+               1,
+               &prop,
+               count as GLsizei,
+               ptr::null_mut(),
+               vars.as_mut_ptr(),
+            );
+         }
+      }
+      let arrays: Vec<(GLint, GLint)> = vars
+         .iter()
+         .map(|var| {
+            let v = resource_props(
+               program,
+               gl::BUFFER_VARIABLE,
+               *var as u32,
+               &[gl::TOP_LEVEL_ARRAY_SIZE, gl::TOP_LEVEL_ARRAY_STRIDE],
+            );
+            (v[0], v[1])
+         })
+         .collect();
+      let stride = match arrays.iter().find(|(size, _)| *size == 0) {
+         Some((_, stride)) => *stride,
+         None => match arrays.first() {
+            Some((_, stride)) if arrays.iter().all(|(_, s)| s == stride) => *stride,
+            _ => 0,
+         },
+      };
+      let stride = stride.max(0) as usize;
+
+      let elem = size_of::<T>();
+      let (expected, what) = match stride {
+         0 => (block_size, "size"),
+         stride => (stride, "stride"),
+      };
+      if expected != elem {
+         return Err(GLueError::from(
+            GLueErrorKind::LayoutMismatch,
+            &format!(
+               "storage block '{name}' has a std430 {what} of {expected} bytes but {} is {elem} bytes",
+               std::any::type_name::<T>()
+            ),
+         ));
+      }
+      if binding >= Slot::total_slots() {
+         return Err(GLueError::from(
+            GLueErrorKind::BindFailed,
+            &format!(
+               "storage block '{name}' uses binding {binding}, only {} slots exist",
+               Slot::total_slots()
+            ),
+         ));
+      }
+      self.storages[binding] = Some(sbo.obj.clone());
+      Ok(())
+   }
+}
+
+fn check_program_interface() -> Result<(), GLueError> {
+   let ctx = Ctx::current();
+   let supported = match ctx.is_es() {
+      true => ctx.at_least(3, 1),
+      false => ctx.at_least(4, 3),
+   };
+   match supported && gl::GetProgramResourceiv::is_loaded() {
+      true => Ok(()),
+      false => Err(GLueError::from(
+         GLueErrorKind::Unsupported,
+         "program interface queries need opengl 4.3 or opengl es 3.1",
+      )),
+   }
+}
+
+fn query_blocks(program: u32, block_interface: GLenum, member_interface: GLenum) -> Vec<BlockInfo> {
+   let mut blocks = Vec::new();
+   for i in 0..resource_count(program, block_interface) {
+      let props = resource_props(
+         program,
+         block_interface,
+         i,
+         &[
+            gl::BUFFER_BINDING,
+            gl::BUFFER_DATA_SIZE,
+            gl::NUM_ACTIVE_VARIABLES,
+         ],
+      );
+      let count = props[2].max(0) as usize;
+      let mut indices = vec![0 as GLint; count];
+      if count > 0 {
+         let prop = gl::ACTIVE_VARIABLES;
+         unsafe {
+            gl::GetProgramResourceiv(
+               program,
+               block_interface,
+               i,
+               1,
+               &prop,
+               count as GLsizei,
+               ptr::null_mut(),
+               indices.as_mut_ptr(),
+            );
+         }
+      }
+
+      let mut members = Vec::with_capacity(count);
+      for index in indices {
+         let index = index as u32;
+         let m = resource_props(
+            program,
+            member_interface,
+            index,
+            &[
+               gl::TYPE,
+               gl::OFFSET,
+               gl::ARRAY_SIZE,
+               gl::ARRAY_STRIDE,
+               gl::MATRIX_STRIDE,
+            ],
+         );
+         members.push(BlockMember {
+            name: resource_name(program, member_interface, index),
+            gl_type: m[0] as GLenum,
+            offset: m[1].max(0) as u32,
+            array_size: m[2].max(0) as u32,
+            array_stride: m[3].max(0) as u32,
+            matrix_stride: m[4].max(0) as u32,
+         });
+      }
+      members.sort_by_key(|m| m.offset);
+
+      blocks.push(BlockInfo {
+         name: resource_name(program, block_interface, i),
+         binding: props[0].max(0) as u32,
+         size: props[1].max(0) as u32,
+         members,
+      });
+   }
+   blocks
+}
+
+fn as_location(location: GLint) -> Option<u32> {
+   match location {
+      -1 => None,
+      l => Some(l as u32),
+   }
+}
+
+//PROGRAM INTERFACE
+fn resource_count(program: u32, interface: GLenum) -> u32 {
+   let mut count: GLint = 0;
+   unsafe {
+      gl::GetProgramInterfaceiv(program, interface, gl::ACTIVE_RESOURCES, &mut count);
+   }
+   count.max(0) as u32
+}
+
+fn resource_name(program: u32, interface: GLenum, index: u32) -> String {
+   let mut max_len: GLint = 0;
+   unsafe {
+      gl::GetProgramInterfaceiv(program, interface, gl::MAX_NAME_LENGTH, &mut max_len);
+   }
+   if max_len <= 0 {
+      return String::new();
+   }
+   let mut name = vec![0u8; max_len as usize];
+   let mut len: GLsizei = 0;
+   unsafe {
+      gl::GetProgramResourceName(
+         program,
+         interface,
+         index,
+         max_len,
+         &mut len,
+         name.as_mut_ptr() as *mut GLchar,
+      );
+   }
+   name.truncate(len.max(0) as usize);
+   String::from_utf8_lossy(&name).to_string()
+}
+
+fn resource_props(program: u32, interface: GLenum, index: u32, props: &[GLenum]) -> Vec<GLint> {
+   let mut values = vec![0 as GLint; props.len()];
+   unsafe {
+      gl::GetProgramResourceiv(
+         program,
+         interface,
+         index,
+         props.len() as GLsizei,
+         props.as_ptr(),
+         values.len() as GLsizei,
+         ptr::null_mut(),
+         values.as_mut_ptr(),
+      );
+   }
+   values
+}
+
+pub fn glsl_type_name(gl_type: GLenum) -> &'static str {
+   match gl_type {
+      gl::FLOAT => "float",
+      gl::FLOAT_VEC2 => "vec2",
+      gl::FLOAT_VEC3 => "vec3",
+      gl::FLOAT_VEC4 => "vec4",
+      gl::DOUBLE => "double",
+      gl::DOUBLE_VEC2 => "dvec2",
+      gl::DOUBLE_VEC3 => "dvec3",
+      gl::DOUBLE_VEC4 => "dvec4",
+      gl::INT => "int",
+      gl::INT_VEC2 => "ivec2",
+      gl::INT_VEC3 => "ivec3",
+      gl::INT_VEC4 => "ivec4",
+      gl::UNSIGNED_INT => "uint",
+      gl::UNSIGNED_INT_VEC2 => "uvec2",
+      gl::UNSIGNED_INT_VEC3 => "uvec3",
+      gl::UNSIGNED_INT_VEC4 => "uvec4",
+      gl::BOOL => "bool",
+      gl::BOOL_VEC2 => "bvec2",
+      gl::BOOL_VEC3 => "bvec3",
+      gl::BOOL_VEC4 => "bvec4",
+      gl::FLOAT_MAT2 => "mat2",
+      gl::FLOAT_MAT3 => "mat3",
+      gl::FLOAT_MAT4 => "mat4",
+      gl::FLOAT_MAT2x3 => "mat2x3",
+      gl::FLOAT_MAT2x4 => "mat2x4",
+      gl::FLOAT_MAT3x2 => "mat3x2",
+      gl::FLOAT_MAT3x4 => "mat3x4",
+      gl::FLOAT_MAT4x2 => "mat4x2",
+      gl::FLOAT_MAT4x3 => "mat4x3",
+      gl::SAMPLER_2D => "sampler2D",
+      gl::SAMPLER_3D => "sampler3D",
+      gl::SAMPLER_CUBE => "samplerCube",
+      gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+      gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+      gl::SAMPLER_2D_MULTISAMPLE => "sampler2DMS",
+      gl::INT_SAMPLER_2D => "isampler2D",
+      gl::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+      gl::IMAGE_2D => "image2D",
+      gl::INT_IMAGE_2D => "iimage2D",
+      gl::UNSIGNED_INT_IMAGE_2D => "uimage2D",
+      _ => "unknown",
+   }
+}
@@ -1,9 +1,16 @@
-use crate::asset::{bind_image_texture2d_at, bind_texture2d_sampler_at, delete_program};
-use crate::renderer::{bind_storage_buffer_at, unbind_storage_buffer};
-use crate::{StorageBuffer, Texture2D};
+use crate::asset::{bind_image_texture2d_at, bind_texture2d_sampler_at};
+use crate::renderer::{
+   GlObject, ObjectKind, bind_storage_buffer_at, collect_garbage, debug_warn, unbind_storage_buffer,
+};
+use crate::{
+   GLueError, GLueErrorKind, HotReload, MissingUniform, StorageBuffer, Texture2D, Uniform,
+};
 use cgmath::{Matrix, Matrix2, Matrix3, Matrix4, Vector2, Vector3, Vector4};
 use gl::types::GLint;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::CString;
+use std::rc::Rc;
 
 pub enum Slot {
    S0,
@@ -100,18 +107,37 @@ impl Workers {
 #[derive(Clone, Debug)]
 pub struct Shader {
    pub workers: Workers,
-   pub(crate) id: u32,
+   pub(crate) obj: Rc<RefCell<GlObject>>,
    pub(crate) is_compute: bool,
-   pub(crate) tex_ids: Vec<Option<u32>>,
-   pub(crate) sbo_ids: Vec<Option<u32>>,
+   // shared handles keep a bound texture or buffer alive while the shader still uses it
+   pub(crate) textures: Vec<Option<Rc<GlObject>>>,
+   pub(crate) storages: Vec<Option<Rc<GlObject>>>,
+   pub(crate) label: Option<String>,
+   pub(crate) locations: Rc<RefCell<HashMap<String, GLint>>>,
+   pub(crate) missing_uniform: MissingUniform,
+   pub(crate) hot_reload: Option<Rc<HotReload>>,
 }
 
 impl Shader {
-   pub fn set_tex_at_slot(&mut self, tex_id: u32, slot: Slot) {
-      self.tex_ids[slot.as_index()] = Some(tex_id)
+   pub(crate) fn from_program(id: u32, is_compute: bool) -> Shader {
+      Shader {
+         workers: Workers::one(),
+         obj: Rc::new(RefCell::new(GlObject::new(ObjectKind::Program, id))),
+         is_compute,
+         textures: vec![None; Slot::total_slots()],
+         storages: vec![None; Slot::total_slots()],
+         label: None,
+         locations: Rc::new(RefCell::new(HashMap::new())),
+         missing_uniform: MissingUniform::default(),
+         hot_reload: None,
+      }
+   }
+
+   pub fn set_tex_at_slot(&mut self, tex: &Texture2D, slot: Slot) {
+      self.textures[slot.as_index()] = Some(tex.obj.clone())
    }
-   pub fn set_sbo_at_slot(&mut self, sb_id: u32, slot: Slot) {
-      self.sbo_ids[slot.as_index()] = Some(sb_id)
+   pub fn set_sbo_at_slot<T>(&mut self, sbo: &StorageBuffer<T>, slot: Slot) {
+      self.storages[slot.as_index()] = Some(sbo.obj.clone())
    }
 
    pub fn storage_binds(&self) -> Vec<(u32, i32)> {
@@ -148,18 +174,59 @@ impl Shader {
       bindings
    }
 
+   pub fn label(&self) -> Option<&str> {
+      self.label.as_deref()
+   }
+   pub fn set_label(&mut self, label: &str) {
+      self.obj.borrow().label(label);
+      self.label = Some(label.to_string());
+   }
+
+   pub fn id(&self) -> u32 {
+      self.obj.borrow().id
+   }
    pub fn delete(self) {
-      delete_program(self.id)
+      drop(self)
+   }
+
+   pub fn is_hot_reload(&self) -> bool {
+      self.hot_reload.is_some()
+   }
+
+   // swaps in a recompiled program for this shader and all of its clones when a watched file
+   // changed, uniforms and block bindings must be set again, on failure the old program stays
+   // and the error is reported once per change
+   pub fn reload_if_changed(&mut self) -> Result<bool, GLueError> {
+      let hot_reload = match &self.hot_reload {
+         None => return Ok(false),
+         Some(hot_reload) => hot_reload.clone(),
+      };
+      if !hot_reload.changed() {
+         return Ok(false);
+      }
+      let id = match hot_reload.rebuild() {
+         Err(e) => return Err(e),
+         Ok(id) => id,
+      };
+      // the old program goes to the garbage queue when it is dropped here
+      self.obj.replace(GlObject::new(ObjectKind::Program, id));
+      self.locations.borrow_mut().clear();
+      match &self.label {
+         None => {}
+         Some(label) => self.obj.borrow().label(label),
+      }
+      Ok(true)
    }
 
    pub fn bind(&self) {
-      unsafe { gl::UseProgram(self.id) }
+      unsafe { gl::UseProgram(self.id()) }
    }
    pub fn unbind(&self) {
       unsafe { gl::UseProgram(0) }
    }
 
    pub fn compute(&self) {
+      collect_garbage();
       self.bind();
       //CLOSURE FN GO HERE
       //AND HERE
@@ -172,112 +239,228 @@ impl Shader {
       }
    }
 
+   pub fn missing_uniform(&self) -> MissingUniform {
+      self.missing_uniform
+   }
+   pub fn set_missing_uniform(&mut self, policy: MissingUniform) {
+      self.missing_uniform = policy
+   }
+   pub fn with_missing_uniform(mut self, policy: MissingUniform) -> Self {
+      self.missing_uniform = policy;
+      self
+   }
+
    pub fn uniform_location(&self, name: &str) -> Option<u32> {
-      unsafe {
-         let c_name = CString::new(name).unwrap();
-         let location = gl::GetUniformLocation(self.id, c_name.as_ptr());
-         if location == -1 {
-            None
-         } else {
-            Some(location as u32)
+      match self.cached_location(name).0 {
+         -1 => None,
+         location => Some(location as u32),
+      }
+   }
+
+   // (location, first lookup)
+   fn cached_location(&self, name: &str) -> (GLint, bool) {
+      match self.locations.borrow().get(name) {
+         Some(location) => return (*location, false),
+         None => {}
+      }
+      let location = match CString::new(name) {
+         Err(_) => -1,
+         Ok(c_name) => unsafe { gl::GetUniformLocation(self.id(), c_name.as_ptr()) },
+      };
+      self
+         .locations
+         .borrow_mut()
+         .insert(name.to_string(), location);
+      (location, true)
+   }
+
+   // applies the missing uniform policy, warnings only show up on the first lookup
+   fn location(&self, name: &str) -> Result<Option<GLint>, GLueError> {
+      let (location, first) = self.cached_location(name);
+      if location != -1 {
+         return Ok(Some(location));
+      }
+      let err = || {
+         GLueError::from(
+            GLueErrorKind::MissingUniform,
+            &format!("uniform '{name}' does not exist or was optimized away"),
+         )
+      };
+      match self.missing_uniform {
+         MissingUniform::Ignore => Ok(None),
+         MissingUniform::Warn => {
+            if first {
+               debug_warn(err());
+            }
+            Ok(None)
          }
+         MissingUniform::Error => Err(err()),
       }
    }
 
-   pub(crate) fn get_uni_location(&self, name: &str) -> GLint {
-      unsafe {
-         let c_name = CString::new(name).unwrap();
-         let location = gl::GetUniformLocation(self.id, c_name.as_ptr());
-         if location == -1 {
-            panic!("uniform '{name}' does not exist!");
-         } else {
-            location
+   pub(crate) fn missing_block(&self, what: &str, name: &str) -> Result<(), GLueError> {
+      let err = GLueError::from(
+         GLueErrorKind::MissingUniform,
+         &format!("{what} '{name}' does not exist or was optimized away"),
+      );
+      match self.missing_uniform {
+         MissingUniform::Error => Err(err),
+         MissingUniform::Warn => {
+            debug_warn(err);
+            Ok(())
+         }
+         MissingUniform::Ignore => Ok(()),
+      }
+   }
+
+   // runs f with the location if the uniform exists, otherwise the policy decides
+   fn with_location<F: FnOnce(GLint)>(&self, name: &str, f: F) -> Result<(), GLueError> {
+      match self.location(name) {
+         Err(e) => Err(e),
+         Ok(None) => Ok(()),
+         Ok(Some(location)) => {
+            f(location);
+            Ok(())
          }
       }
    }
 
+   pub fn set_uniform<T: Uniform>(&mut self, name: &str, value: T) -> Result<(), GLueError> {
+      let location = match self.location(name) {
+         Err(e) => return Err(e),
+         Ok(None) => return Ok(()),
+         Ok(Some(location)) => location,
+      };
+      self.bind();
+      value.set_uniform(self, location)
+   }
+
+   pub(crate) fn texture_slot(&mut self, tex: &Texture2D) -> Result<u32, GLueError> {
+      let same = |t: &Option<Rc<GlObject>>| match t {
+         None => false,
+         Some(obj) => Rc::ptr_eq(obj, &tex.obj),
+      };
+      let slot = match self.textures.iter().position(same) {
+         Some(slot) => slot,
+         None => match self.textures.iter().position(|t| t.is_none()) {
+            Some(slot) => slot,
+            None => {
+               return Err(GLueError::from(
+                  GLueErrorKind::BindFailed,
+                  &format!(
+                     "all {} texture slots are taken, cannot bind texture {}",
+                     Slot::total_slots(),
+                     tex.id()
+                  ),
+               ));
+            }
+         },
+      };
+      self.textures[slot] = Some(tex.obj.clone());
+      Ok(slot as u32)
+   }
+
+   pub(crate) fn try_set_uni_m4_f32(&self, name: &str, m: Matrix4<f32>) {
+      match self.uniform_location(name) {
+         None => {}
+         Some(loc) => unsafe { gl::UniformMatrix4fv(loc as GLint, 1, gl::FALSE, m.as_ptr()) },
+      }
+   }
+
    pub(crate) fn bind_textures(&self) {
-      for (slot, tex_id) in self.tex_ids.iter().enumerate() {
-         match tex_id {
+      for (slot, tex) in self.textures.iter().enumerate() {
+         match tex {
             None => {}
-            Some(id) => match self.is_compute {
-               false => bind_texture2d_sampler_at(*id, slot as u32),
-               true => bind_image_texture2d_at(*id, slot as u32),
+            Some(obj) => match self.is_compute {
+               false => bind_texture2d_sampler_at(obj.id, slot as u32),
+               true => bind_image_texture2d_at(obj.id, slot as u32),
             },
          }
       }
    }
    pub(crate) fn bind_storages(&self) {
-      for (slot, sbo_id) in self.sbo_ids.iter().enumerate() {
-         match sbo_id {
+      for (slot, sbo) in self.storages.iter().enumerate() {
+         match sbo {
             None => {}
-            Some(id) => bind_storage_buffer_at(*id, slot as u32),
+            Some(obj) => bind_storage_buffer_at(obj.id, slot as u32),
          }
       }
    }
 
    // ---- scalar ----
-   pub fn set_uni_i32(&self, name: &str, v: i32) {
-      unsafe { gl::Uniform1i(self.get_uni_location(name), v) }
+   pub fn set_uni_i32(&self, name: &str, v: i32) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe { gl::Uniform1i(loc, v) })
    }
 
-   pub fn set_uni_u32(&self, name: &str, v: u32) {
-      unsafe { gl::Uniform1ui(self.get_uni_location(name), v) }
+   pub fn set_uni_u32(&self, name: &str, v: u32) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe { gl::Uniform1ui(loc, v) })
    }
 
-   pub fn set_uni_f32(&self, name: &str, v: f32) {
-      unsafe { gl::Uniform1f(self.get_uni_location(name), v) }
+   pub fn set_uni_f32(&self, name: &str, v: f32) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe { gl::Uniform1f(loc, v) })
    }
 
    // ---- vec2 ----
-   pub fn set_uni_vec2_i32(&self, name: &str, v: Vector2<i32>) {
-      unsafe { gl::Uniform2i(self.get_uni_location(name), v.x, v.y) }
+   pub fn set_uni_vec2_i32(&self, name: &str, v: Vector2<i32>) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe { gl::Uniform2i(loc, v.x, v.y) })
    }
 
-   pub fn set_uni_vec2_u32(&self, name: &str, v: Vector2<u32>) {
-      unsafe { gl::Uniform2ui(self.get_uni_location(name), v.x, v.y) }
+   pub fn set_uni_vec2_u32(&self, name: &str, v: Vector2<u32>) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe { gl::Uniform2ui(loc, v.x, v.y) })
    }
 
-   pub fn set_uni_vec2_f32(&self, name: &str, v: Vector2<f32>) {
-      unsafe { gl::Uniform2f(self.get_uni_location(name), v.x, v.y) }
+   pub fn set_uni_vec2_f32(&self, name: &str, v: Vector2<f32>) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe { gl::Uniform2f(loc, v.x, v.y) })
    }
 
    // ---- vec3 ----
-   pub fn set_uni_vec3_i32(&self, name: &str, v: Vector3<i32>) {
-      unsafe { gl::Uniform3i(self.get_uni_location(name), v.x, v.y, v.z) }
+   pub fn set_uni_vec3_i32(&self, name: &str, v: Vector3<i32>) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe { gl::Uniform3i(loc, v.x, v.y, v.z) })
    }
 
-   pub fn set_uni_vec3_u32(&self, name: &str, v: Vector3<u32>) {
-      unsafe { gl::Uniform3ui(self.get_uni_location(name), v.x, v.y, v.z) }
+   pub fn set_uni_vec3_u32(&self, name: &str, v: Vector3<u32>) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe { gl::Uniform3ui(loc, v.x, v.y, v.z) })
    }
 
-   pub fn set_uni_vec3_f32(&self, name: &str, v: Vector3<f32>) {
-      unsafe { gl::Uniform3f(self.get_uni_location(name), v.x, v.y, v.z) }
+   pub fn set_uni_vec3_f32(&self, name: &str, v: Vector3<f32>) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe { gl::Uniform3f(loc, v.x, v.y, v.z) })
    }
 
    // ---- vec4 ----
-   pub fn set_uni_vec4_i32(&self, name: &str, v: Vector4<i32>) {
-      unsafe { gl::Uniform4i(self.get_uni_location(name), v.x, v.y, v.z, v.w) }
+   pub fn set_uni_vec4_i32(&self, name: &str, v: Vector4<i32>) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe {
+         gl::Uniform4i(loc, v.x, v.y, v.z, v.w)
+      })
    }
 
-   pub fn set_uni_vec4_u32(&self, name: &str, v: Vector4<u32>) {
-      unsafe { gl::Uniform4ui(self.get_uni_location(name), v.x, v.y, v.z, v.w) }
+   pub fn set_uni_vec4_u32(&self, name: &str, v: Vector4<u32>) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe {
+         gl::Uniform4ui(loc, v.x, v.y, v.z, v.w)
+      })
    }
 
-   pub fn set_uni_vec4_f32(&self, name: &str, v: Vector4<f32>) {
-      unsafe { gl::Uniform4f(self.get_uni_location(name), v.x, v.y, v.z, v.w) }
+   pub fn set_uni_vec4_f32(&self, name: &str, v: Vector4<f32>) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe {
+         gl::Uniform4f(loc, v.x, v.y, v.z, v.w)
+      })
    }
 
    // ---- matrices ----
-   pub fn set_uni_m2_f32(&self, name: &str, m: Matrix2<f32>) {
-      unsafe { gl::UniformMatrix2fv(self.get_uni_location(name), 1, gl::FALSE, m.as_ptr()) }
+   pub fn set_uni_m2_f32(&self, name: &str, m: Matrix2<f32>) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe {
+         gl::UniformMatrix2fv(loc, 1, gl::FALSE, m.as_ptr())
+      })
    }
 
-   pub fn set_uni_m3_f32(&self, name: &str, m: Matrix3<f32>) {
-      unsafe { gl::UniformMatrix3fv(self.get_uni_location(name), 1, gl::FALSE, m.as_ptr()) }
+   pub fn set_uni_m3_f32(&self, name: &str, m: Matrix3<f32>) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe {
+         gl::UniformMatrix3fv(loc, 1, gl::FALSE, m.as_ptr())
+      })
    }
 
-   pub fn set_uni_m4_f32(&self, name: &str, m: Matrix4<f32>) {
-      unsafe { gl::UniformMatrix4fv(self.get_uni_location(name), 1, gl::FALSE, m.as_ptr()) }
+   pub fn set_uni_m4_f32(&self, name: &str, m: Matrix4<f32>) -> Result<(), GLueError> {
+      self.with_location(name, |loc| unsafe {
+         gl::UniformMatrix4fv(loc, 1, gl::FALSE, m.as_ptr())
+      })
    }
 }
@@ -0,0 +1,195 @@
+use crate::asset::{flip_rows, read_pixel_size};
+use crate::renderer::glraw::Ctx;
+use crate::renderer::{GlObject, ObjectKind, queue_sync};
+use crate::{Image, ImgFormat, Size2D};
+use gl::types::{GLenum, GLsizeiptr, GLsync};
+use std::ptr;
+use std::time::Duration;
+
+pub struct Fence {
+   pub(crate) sync: GLsync,
+   generation: u32,
+}
+
+impl Fence {
+   pub fn new() -> Fence {
+      let sync = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+      Fence {
+         sync,
+         generation: Ctx::current().generation,
+      }
+   }
+
+   pub fn is_signaled(&self) -> bool {
+      self.wait(Duration::ZERO)
+   }
+
+   pub fn wait(&self, timeout: Duration) -> bool {
+      if self.sync.is_null() {
+         return true;
+      }
+      let nanos = timeout.as_nanos().min(u64::MAX as u128) as u64;
+      let status = unsafe { gl::ClientWaitSync(self.sync, gl::SYNC_FLUSH_COMMANDS_BIT, nanos) };
+      match status {
+         gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => true,
+         _ => false,
+      }
+   }
+
+   pub fn delete(self) {
+      drop(self)
+   }
+}
+
+impl Default for Fence {
+   fn default() -> Fence {
+      Fence::new()
+   }
+}
+
+impl Drop for Fence {
+   fn drop(&mut self) {
+      if !self.sync.is_null() {
+         queue_sync(self.sync, self.generation);
+      }
+   }
+}
+
+// the staging buffer is a GlObject so a dropped read is freed like any other handle
+pub struct PendingRead<T> {
+   pub(crate) fence: Fence,
+   pub(crate) buf: GlObject,
+   pub(crate) count: usize,
+   _phantom: std::marker::PhantomData<T>,
+}
+
+impl<T> PendingRead<T>
+where
+   T: Default + Clone,
+{
+   pub(crate) fn from_buffer(buf_id: u32, count: usize) -> PendingRead<T> {
+      PendingRead {
+         fence: Fence::new(),
+         buf: GlObject::new(ObjectKind::Buffer, buf_id),
+         count,
+         _phantom: std::marker::PhantomData,
+      }
+   }
+
+   pub fn count(&self) -> usize {
+      self.count
+   }
+   pub fn is_ready(&self) -> bool {
+      self.fence.is_signaled()
+   }
+
+   pub fn poll(&self) -> Option<Vec<T>> {
+      self.wait(Duration::ZERO)
+   }
+
+   pub fn wait(&self, timeout: Duration) -> Option<Vec<T>> {
+      match self.fence.wait(timeout) {
+         false => None,
+         true => Some(read_back_buffer(self.buf.id, self.count)),
+      }
+   }
+
+   pub fn delete(self) {
+      drop(self)
+   }
+}
+
+pub struct PendingImage {
+   pub(crate) read: PendingRead<u8>,
+   pub(crate) size: Size2D,
+   pub(crate) fmt: ImgFormat,
+}
+
+impl PendingImage {
+   pub fn size(&self) -> Size2D {
+      self.size
+   }
+   pub fn fmt(&self) -> ImgFormat {
+      self.fmt
+   }
+   pub fn is_ready(&self) -> bool {
+      self.read.is_ready()
+   }
+
+   pub fn poll(&self) -> Option<Image> {
+      self.wait(Duration::ZERO)
+   }
+
+   pub fn wait(&self, timeout: Duration) -> Option<Image> {
+      let mut bytes = self.read.wait(timeout)?;
+      let row_size = self.size.w as usize * read_pixel_size(&self.fmt);
+      if row_size > 0 {
+         flip_rows(&mut bytes, row_size);
+      }
+      Some(Image::from_bytes(bytes, self.size, self.fmt))
+   }
+
+   pub fn delete(self) {
+      self.read.delete()
+   }
+}
+
+//READ BUFFERS
+pub(crate) fn create_read_buffer(target: GLenum, size: usize) -> u32 {
+   let mut id: u32 = 0;
+   unsafe {
+      gl::GenBuffers(1, &mut id);
+      gl::BindBuffer(target, id);
+      gl::BufferData(target, size as GLsizeiptr, ptr::null(), gl::STREAM_READ);
+   }
+   id
+}
+
+pub(crate) fn read_back_buffer<T>(id: u32, count: usize) -> Vec<T>
+where
+   T: Default + Clone,
+{
+   let mut data = vec![T::default(); count];
+   if count == 0 {
+      return data;
+   }
+   unsafe {
+      gl::BindBuffer(gl::COPY_READ_BUFFER, id);
+      let size = (count * size_of::<T>()) as GLsizeiptr;
+      let mapped = gl::MapBufferRange(gl::COPY_READ_BUFFER, 0, size, gl::MAP_READ_BIT);
+      if !mapped.is_null() {
+         ptr::copy_nonoverlapping(
+            mapped as *const u8,
+            data.as_mut_ptr() as *mut u8,
+            size as usize,
+         );
+         gl::UnmapBuffer(gl::COPY_READ_BUFFER);
+      }
+      gl::BindBuffer(gl::COPY_READ_BUFFER, 0);
+   }
+   data
+}
+
+pub(crate) fn copy_buffer(src_id: u32, dst_id: u32, size: usize) {
+   unsafe {
+      gl::BindBuffer(gl::COPY_READ_BUFFER, src_id);
+      gl::BindBuffer(gl::COPY_WRITE_BUFFER, dst_id);
+      gl::CopyBufferSubData(
+         gl::COPY_READ_BUFFER,
+         gl::COPY_WRITE_BUFFER,
+         0,
+         0,
+         size as GLsizeiptr,
+      );
+      gl::BindBuffer(gl::COPY_READ_BUFFER, 0);
+      gl::BindBuffer(gl::COPY_WRITE_BUFFER, 0);
+   }
+}
+
+pub(crate) fn pack_barrier() {
+   if gl::MemoryBarrier::is_loaded() {
+      unsafe {
+         gl::MemoryBarrier(gl::PIXEL_BUFFER_BARRIER_BIT | gl::FRAMEBUFFER_BARRIER_BIT);
+      }
+   }
+}
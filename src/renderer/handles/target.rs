@@ -0,0 +1,426 @@
+use crate::asset::{
+   create_empty_texture2d, match_tex_fmt, read_framebuffer, read_framebuffer_async,
+};
+use crate::renderer::glraw::Ctx;
+use crate::renderer::{GlObject, ObjectKind};
+use crate::{
+   GLueError, GLueErrorKind, Image, ImgFilter, ImgFormat, ImgWrap, PendingImage, RenderState,
+   Size2D, Texture2D,
+};
+use gl::types::{GLenum, GLsizei};
+use kolor::RGBA;
+
+#[derive(Clone, Debug)]
+pub struct TargetConfig {
+   pub(crate) size: Size2D,
+   pub(crate) colors: Vec<ImgFormat>,
+   pub(crate) depth: Option<ImgFormat>,
+   pub(crate) samples: u32,
+   pub(crate) filter: ImgFilter,
+   pub(crate) wrap: ImgWrap,
+}
+
+impl TargetConfig {
+   pub fn new(size: Size2D) -> TargetConfig {
+      TargetConfig {
+         size,
+         colors: Vec::new(),
+         depth: None,
+         samples: 1,
+         filter: ImgFilter::Linear,
+         wrap: ImgWrap::Extend,
+      }
+   }
+
+   pub fn with_color(mut self, fmt: ImgFormat) -> TargetConfig {
+      self.colors.push(fmt);
+      self
+   }
+   pub fn with_depth(mut self, fmt: ImgFormat) -> TargetConfig {
+      self.depth = Some(fmt);
+      self
+   }
+   pub fn with_samples(mut self, samples: u32) -> TargetConfig {
+      self.samples = samples.max(1);
+      self
+   }
+   pub fn with_filter(mut self, filter: ImgFilter) -> TargetConfig {
+      self.filter = filter;
+      self
+   }
+   pub fn with_wrap(mut self, wrap: ImgWrap) -> TargetConfig {
+      self.wrap = wrap;
+      self
+   }
+
+   pub fn ship(self) -> Result<RenderTarget, GLueError> {
+      for fmt in self.colors.iter() {
+         if fmt.is_depth() {
+            return Err(GLueError::from(
+               GLueErrorKind::IncompleteTarget,
+               &format!("{fmt:?} is not a color format"),
+            ));
+         }
+      }
+      match self.depth {
+         Some(fmt) if !fmt.is_depth() => {
+            return Err(GLueError::from(
+               GLueErrorKind::IncompleteTarget,
+               &format!("{fmt:?} is not a depth format"),
+            ));
+         }
+         _ => {}
+      }
+
+      let fbo_id = create_framebuffer();
+      bind_framebuffer(fbo_id);
+
+      let mut colors = Vec::new();
+      for (i, fmt) in self.colors.iter().enumerate() {
+         let id = create_empty_texture2d(self.size, fmt, &self.filter, &self.wrap);
+         attach_texture(gl::COLOR_ATTACHMENT0 + i as u32, id);
+         colors.push(Texture2D {
+            obj: GlObject::shared(ObjectKind::Texture, id),
+            size: self.size,
+            fmt: *fmt,
+            filter: self.filter,
+            wrap: self.wrap,
+            label: None,
+         });
+      }
+      let depth = match self.depth {
+         None => None,
+         Some(fmt) => {
+            let id = create_empty_texture2d(self.size, &fmt, &ImgFilter::Closest, &ImgWrap::Extend);
+            attach_texture(match_depth_attachment(&fmt), id);
+            Some(Texture2D {
+               obj: GlObject::shared(ObjectKind::Texture, id),
+               size: self.size,
+               fmt,
+               filter: ImgFilter::Closest,
+               wrap: ImgWrap::Extend,
+               label: None,
+            })
+         }
+      };
+      set_draw_buffers(colors.len());
+
+      let mut target = RenderTarget {
+         fbo: GlObject::new(ObjectKind::Framebuffer, fbo_id),
+         msaa_fbo: None,
+         rbos: Vec::new(),
+         size: self.size,
+         samples: 1,
+         colors,
+         depth,
+         label: None,
+      };
+      match framebuffer_incomplete() {
+         Err(e) => {
+            unbind_framebuffer();
+            target.delete();
+            return Err(e);
+         }
+         Ok(_) => {}
+      }
+
+      if self.samples > 1 {
+         let msaa_fbo_id = create_framebuffer();
+         bind_framebuffer(msaa_fbo_id);
+         target.msaa_fbo = Some(GlObject::new(ObjectKind::Framebuffer, msaa_fbo_id));
+         target.samples = self.samples;
+
+         for (i, fmt) in self.colors.iter().enumerate() {
+            let id = create_msaa_renderbuffer(self.size, fmt, self.samples);
+            attach_renderbuffer(gl::COLOR_ATTACHMENT0 + i as u32, id);
+            target
+               .rbos
+               .push(GlObject::new(ObjectKind::Renderbuffer, id));
+         }
+         match self.depth {
+            None => {}
+            Some(fmt) => {
+               let id = create_msaa_renderbuffer(self.size, &fmt, self.samples);
+               attach_renderbuffer(match_depth_attachment(&fmt), id);
+               target
+                  .rbos
+                  .push(GlObject::new(ObjectKind::Renderbuffer, id));
+            }
+         }
+         set_draw_buffers(self.colors.len());
+
+         match framebuffer_incomplete() {
+            Err(e) => {
+               unbind_framebuffer();
+               target.delete();
+               return Err(e);
+            }
+            Ok(_) => {}
+         }
+      }
+      unbind_framebuffer();
+      Ok(target)
+   }
+}
+
+#[derive(Debug)]
+pub struct RenderTarget {
+   pub(crate) fbo: GlObject,
+   pub(crate) msaa_fbo: Option<GlObject>,
+   pub(crate) rbos: Vec<GlObject>,
+   pub(crate) size: Size2D,
+   pub(crate) samples: u32,
+   pub(crate) colors: Vec<Texture2D>,
+   pub(crate) depth: Option<Texture2D>,
+   pub(crate) label: Option<String>,
+}
+
+impl RenderTarget {
+   pub fn id(&self) -> u32 {
+      self.fbo.id
+   }
+   pub fn size(&self) -> Size2D {
+      self.size
+   }
+   pub fn samples(&self) -> u32 {
+      self.samples
+   }
+   pub fn is_msaa(&self) -> bool {
+      self.msaa_fbo.is_some()
+   }
+
+   pub fn colors(&self) -> &[Texture2D] {
+      &self.colors
+   }
+   pub fn color(&self, index: usize) -> Option<Texture2D> {
+      self.colors.get(index).cloned()
+   }
+   pub fn depth(&self) -> Option<Texture2D> {
+      self.depth.clone()
+   }
+
+   pub fn label(&self) -> Option<&str> {
+      self.label.as_deref()
+   }
+   pub fn set_label(&mut self, label: &str) {
+      self.fbo.label(label);
+      match &self.msaa_fbo {
+         None => {}
+         Some(fbo) => fbo.label(&format!("{label} (msaa)")),
+      }
+      for (i, rbo) in self.rbos.iter().enumerate() {
+         rbo.label(&format!("{label} (msaa) attachment {i}"));
+      }
+      for (i, color) in self.colors.iter_mut().enumerate() {
+         color.set_label(&format!("{label} color {i}"));
+      }
+      match &mut self.depth {
+         None => {}
+         Some(depth) => depth.set_label(&format!("{label} depth")),
+      }
+      self.label = Some(label.to_string());
+   }
+
+   pub(crate) fn draw_fbo_id(&self) -> u32 {
+      match &self.msaa_fbo {
+         None => self.fbo.id,
+         Some(fbo) => fbo.id,
+      }
+   }
+
+   pub fn bind(&self) {
+      bind_framebuffer(self.draw_fbo_id());
+      unsafe {
+         gl::Viewport(0, 0, self.size.w as i32, self.size.h as i32);
+      }
+   }
+   pub fn unbind(&self) {
+      unbind_framebuffer()
+   }
+
+   pub fn clear(&self, color: RGBA) {
+      self.bind();
+      RenderState::for_clear().apply();
+      unsafe {
+         // the main surface keeps clearing to the bg color
+         let mut prev = [0.0f32; 4];
+         gl::GetFloatv(gl::COLOR_CLEAR_VALUE, prev.as_mut_ptr());
+         gl::ClearColor(color.r(), color.g(), color.b(), color.a());
+         gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+         gl::ClearColor(prev[0], prev[1], prev[2], prev[3]);
+      }
+   }
+
+   pub fn resolve(&self) {
+      let msaa_fbo_id = match &self.msaa_fbo {
+         None => return,
+         Some(fbo) => fbo.id,
+      };
+      let (w, h) = (self.size.w as i32, self.size.h as i32);
+      RenderState::for_clear().apply();
+      unsafe {
+         gl::BindFramebuffer(gl::READ_FRAMEBUFFER, msaa_fbo_id);
+         gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.fbo.id);
+         for i in 0..self.colors.len() {
+            let attachment = gl::COLOR_ATTACHMENT0 + i as u32;
+            gl::ReadBuffer(attachment);
+            gl::DrawBuffers(1, &attachment);
+            gl::BlitFramebuffer(0, 0, w, h, 0, 0, w, h, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+         }
+         match &self.depth {
+            None => {}
+            Some(depth) => {
+               let mut mask = gl::DEPTH_BUFFER_BIT;
+               if depth.fmt.has_stencil() {
+                  mask |= gl::STENCIL_BUFFER_BIT;
+               }
+               gl::BlitFramebuffer(0, 0, w, h, 0, 0, w, h, mask, gl::NEAREST);
+            }
+         }
+      }
+      bind_framebuffer(self.fbo.id);
+      set_draw_buffers(self.colors.len());
+      unbind_framebuffer();
+   }
+
+   pub fn snapshot(&self, index: usize) -> Result<Option<Image>, GLueError> {
+      let fmt = match self.colors.get(index) {
+         None => return Ok(None),
+         Some(color) => color.fmt,
+      };
+      self.resolve();
+      bind_framebuffer(self.fbo.id);
+      unsafe {
+         gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + index as u32);
+      }
+      let bytes = read_framebuffer(self.size, &fmt);
+      unbind_framebuffer();
+      match bytes {
+         Err(e) => Err(e),
+         Ok(bytes) => Ok(Some(Image::from_bytes(bytes, self.size, fmt))),
+      }
+   }
+   pub fn snapshot_async(&self, index: usize) -> Result<Option<PendingImage>, GLueError> {
+      let fmt = match self.colors.get(index) {
+         None => return Ok(None),
+         Some(color) => color.fmt,
+      };
+      self.resolve();
+      bind_framebuffer(self.fbo.id);
+      unsafe {
+         gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + index as u32);
+      }
+      let pending = read_framebuffer_async(self.size, &fmt);
+      unbind_framebuffer();
+      match pending {
+         Err(e) => Err(e),
+         Ok(pending) => Ok(Some(pending)),
+      }
+   }
+
+   pub fn delete(self) {
+      drop(self)
+   }
+}
+
+pub(crate) fn match_depth_attachment(fmt: &ImgFormat) -> GLenum {
+   match fmt.has_stencil() {
+      true => gl::DEPTH_STENCIL_ATTACHMENT,
+      false => gl::DEPTH_ATTACHMENT,
+   }
+}
+
+//FBO
+pub(crate) fn create_framebuffer() -> u32 {
+   let mut id: u32 = 0;
+   unsafe {
+      gl::GenFramebuffers(1, &mut id);
+   }
+   id
+}
+
+pub(crate) fn bind_framebuffer(id: u32) {
+   unsafe {
+      gl::BindFramebuffer(gl::FRAMEBUFFER, id);
+   }
+}
+
+pub(crate) fn unbind_framebuffer() {
+   unsafe {
+      gl::BindFramebuffer(gl::FRAMEBUFFER, Ctx::current().default_fbo);
+   }
+}
+
+pub(crate) fn attach_texture(attachment: GLenum, tex_id: u32) {
+   unsafe {
+      gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, gl::TEXTURE_2D, tex_id, 0);
+   }
+}
+
+pub(crate) fn set_draw_buffers(count: usize) {
+   unsafe {
+      if count == 0 {
+         gl::DrawBuffers(1, &gl::NONE);
+         gl::ReadBuffer(gl::NONE);
+      } else {
+         let buffers: Vec<GLenum> = (0..count)
+            .map(|i| gl::COLOR_ATTACHMENT0 + i as u32)
+            .collect();
+         gl::DrawBuffers(count as GLsizei, buffers.as_ptr());
+         gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+      }
+   }
+}
+
+pub(crate) fn framebuffer_incomplete() -> Result<(), GLueError> {
+   let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+   let reason = match status {
+      gl::FRAMEBUFFER_COMPLETE => return Ok(()),
+      gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => "incomplete attachment",
+      gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => "missing attachment",
+      gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => "mismatched samples",
+      gl::FRAMEBUFFER_UNSUPPORTED => "unsupported format combination",
+      _ => "unknown status",
+   };
+   Err(GLueError::from(
+      GLueErrorKind::IncompleteTarget,
+      &format!("render target incomplete: {reason} ({status:#x})"),
+   ))
+}
+
+pub(crate) fn delete_framebuffer(id: u32) {
+   unsafe {
+      gl::DeleteFramebuffers(1, &id);
+   }
+}
+
+//RBO
+pub(crate) fn create_msaa_renderbuffer(size: Size2D, fmt: &ImgFormat, samples: u32) -> u32 {
+   let (_, sized) = match_tex_fmt(fmt);
+   let mut id: u32 = 0;
+   unsafe {
+      gl::GenRenderbuffers(1, &mut id);
+      gl::BindRenderbuffer(gl::RENDERBUFFER, id);
+      gl::RenderbufferStorageMultisample(
+         gl::RENDERBUFFER,
+         samples as GLsizei,
+         sized,
+         size.w as GLsizei,
+         size.h as GLsizei,
+      );
+      gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+   }
+   id
+}
+
+pub(crate) fn attach_renderbuffer(attachment: GLenum, rbo_id: u32) {
+   unsafe {
+      gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, attachment, gl::RENDERBUFFER, rbo_id);
+   }
+}
+
+pub(crate) fn delete_renderbuffer(id: u32) {
+   unsafe {
+      gl::DeleteRenderbuffers(1, &id);
+   }
+}
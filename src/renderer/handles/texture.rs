@@ -1,12 +1,19 @@
-use crate::asset::delete_texture2d;
-use crate::{Image, Size2D};
+use crate::asset::read_framebuffer_async;
+use crate::renderer::{
+   GlObject, attach_texture, bind_framebuffer, create_framebuffer, delete_framebuffer,
+   match_depth_attachment, unbind_framebuffer,
+};
+use crate::{GLueError, Image, PendingImage, Size2D};
+use std::rc::Rc;
 
-#[derive(Debug, Clone)]
-pub(crate) enum ImgFormat {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImgFormat {
    R(u8), //(bit depth)
    RG(u8),
    RGB(u8),
    RGBA(u8),
+   Depth(u8),        //16, 24 or 32(float)
+   DepthStencil(u8), //24 or 32(float) + 8 stencil
 }
 
 impl ImgFormat {
@@ -16,6 +23,8 @@ impl ImgFormat {
          ImgFormat::RG(_) => 2,
          ImgFormat::RGB(_) => 3,
          ImgFormat::RGBA(_) => 4,
+         ImgFormat::Depth(_) => 1,
+         ImgFormat::DepthStencil(_) => 2,
       }
    }
    pub(crate) fn bit_depth(&self) -> u8 {
@@ -24,10 +33,32 @@ impl ImgFormat {
          ImgFormat::RG(bd) => bd,
          ImgFormat::RGB(bd) => bd,
          ImgFormat::RGBA(bd) => bd,
+         ImgFormat::Depth(bd) => bd,
+         ImgFormat::DepthStencil(bd) => bd,
       }
    }
-   pub(crate) fn pixel_size(&self) -> u8 {
-      self.channels() * self.bit_depth()
+   pub(crate) fn pixel_size(&self) -> usize {
+      self.channels() as usize * self.bit_depth() as usize
+   }
+   pub(crate) fn bytes_per_pixel(&self) -> usize {
+      match self {
+         ImgFormat::Depth(_) => 4,
+         ImgFormat::DepthStencil(32) => 8,
+         ImgFormat::DepthStencil(_) => 4,
+         _ => self.pixel_size() / 8,
+      }
+   }
+   pub(crate) fn is_depth(&self) -> bool {
+      match self {
+         ImgFormat::Depth(_) | ImgFormat::DepthStencil(_) => true,
+         _ => false,
+      }
+   }
+   pub(crate) fn has_stencil(&self) -> bool {
+      match self {
+         ImgFormat::DepthStencil(_) => true,
+         _ => false,
+      }
    }
 
    pub(crate) fn from(channels: u8, bit_depth: u8) -> ImgFormat {
@@ -55,16 +86,17 @@ pub enum ImgWrap {
 
 #[derive(Clone, Debug)]
 pub struct Texture2D {
-   pub(crate) id: u32,
+   pub(crate) obj: Rc<GlObject>,
    pub(crate) size: Size2D,
    pub(crate) fmt: ImgFormat,
    pub(crate) filter: ImgFilter,
    pub(crate) wrap: ImgWrap,
+   pub(crate) label: Option<String>,
 }
 
 impl Texture2D {
    pub fn id(&self) -> u32 {
-      self.id
+      self.obj.id
    }
 }
 
@@ -72,6 +104,9 @@ impl Texture2D {
    pub fn size(&self) -> Size2D {
       self.size
    }
+   pub fn fmt(&self) -> ImgFormat {
+      self.fmt
+   }
 
    pub fn wrap(&self) -> ImgWrap {
       self.wrap
@@ -86,7 +121,31 @@ impl Texture2D {
    pub fn set_filter(&mut self, filter: ImgFilter) {
       self.filter = filter
    }
+   pub fn label(&self) -> Option<&str> {
+      self.label.as_deref()
+   }
+   pub fn set_label(&mut self, label: &str) {
+      self.obj.label(label);
+      self.label = Some(label.to_string());
+   }
+   pub fn fetch_async(&self) -> Result<PendingImage, GLueError> {
+      let fbo_id = create_framebuffer();
+      bind_framebuffer(fbo_id);
+      match self.fmt.is_depth() {
+         true => attach_texture(match_depth_attachment(&self.fmt), self.id()),
+         false => {
+            attach_texture(gl::COLOR_ATTACHMENT0, self.id());
+            unsafe {
+               gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            }
+         }
+      }
+      let pending = read_framebuffer_async(self.size, &self.fmt);
+      unbind_framebuffer();
+      delete_framebuffer(fbo_id);
+      pending
+   }
    pub fn delete(self) {
-      delete_texture2d(self.id)
+      drop(self)
    }
 }
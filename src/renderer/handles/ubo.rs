@@ -0,0 +1,142 @@
+use crate::renderer::{GlObject, ObjectKind};
+use crate::{GLueError, GLueErrorKind, Shader, Std140, Std140Writer};
+use gl::types::GLsizeiptr;
+use std::ffi::{CString, c_void};
+use std::ptr;
+
+pub struct UniformBuffer<T> {
+   pub(crate) obj: GlObject,
+   pub(crate) binding: Option<u32>,
+   pub(crate) label: Option<String>,
+   _phantom: std::marker::PhantomData<T>,
+}
+
+impl<T: Std140> UniformBuffer<T> {
+   pub fn new() -> Self {
+      let id = create_uniform_buffer(T::SIZE);
+      UniformBuffer {
+         obj: GlObject::new(ObjectKind::Buffer, id),
+         binding: None,
+         label: None,
+         _phantom: std::marker::PhantomData,
+      }
+   }
+
+   pub fn from_value(value: &T) -> Self {
+      let ubo = UniformBuffer::new();
+      ubo.upload(value);
+      ubo
+   }
+
+   pub fn with_binding(mut self, binding: u32) -> Self {
+      self.bind_at(binding);
+      self
+   }
+
+   pub fn upload(&self, value: &T) {
+      let bytes = Std140Writer::bytes_of(value);
+      fill_uniform_buffer(self.id(), &bytes);
+   }
+
+   pub fn bind_at(&mut self, binding: u32) {
+      self.binding = Some(binding);
+      bind_uniform_buffer_at(self.id(), binding);
+   }
+
+   pub fn binding(&self) -> Option<u32> {
+      self.binding
+   }
+   pub fn byte_size(&self) -> usize {
+      T::SIZE
+   }
+}
+
+impl<T: Std140> Default for UniformBuffer<T> {
+   fn default() -> Self {
+      UniformBuffer::new()
+   }
+}
+
+impl<T> UniformBuffer<T> {
+   pub fn id(&self) -> u32 {
+      self.obj.id
+   }
+
+   pub fn label(&self) -> Option<&str> {
+      self.label.as_deref()
+   }
+   pub fn set_label(&mut self, label: &str) {
+      self.obj.label(label);
+      self.label = Some(label.to_string());
+   }
+
+   pub fn delete(self) {
+      drop(self)
+   }
+}
+
+impl Shader {
+   pub fn set_block_binding(&self, name: &str, binding: u32) -> Result<(), GLueError> {
+      let index = match CString::new(name) {
+         Err(_) => gl::INVALID_INDEX,
+         Ok(c_name) => unsafe { gl::GetUniformBlockIndex(self.id(), c_name.as_ptr()) },
+      };
+      if index == gl::INVALID_INDEX {
+         return self.missing_block("uniform block", name);
+      }
+      unsafe {
+         gl::UniformBlockBinding(self.id(), index, binding);
+      }
+      Ok(())
+   }
+
+   pub fn set_uniform_buffer<T>(
+      &self,
+      name: &str,
+      ubo: &UniformBuffer<T>,
+   ) -> Result<(), GLueError> {
+      match ubo.binding {
+         None => Err(GLueError::from(
+            GLueErrorKind::BindFailed,
+            &format!("uniform buffer for '{name}' has no binding"),
+         )),
+         Some(binding) => self.set_block_binding(name, binding),
+      }
+   }
+}
+
+//UBO
+pub(crate) fn create_uniform_buffer(size: usize) -> u32 {
+   let mut id: u32 = 0;
+   unsafe {
+      gl::GenBuffers(1, &mut id);
+      gl::BindBuffer(gl::UNIFORM_BUFFER, id);
+      gl::BufferData(
+         gl::UNIFORM_BUFFER,
+         size as GLsizeiptr,
+         ptr::null(),
+         gl::DYNAMIC_DRAW,
+      );
+      gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+   }
+   id
+}
+
+pub(crate) fn fill_uniform_buffer(id: u32, data: &[u8]) {
+   unsafe {
+      gl::BindBuffer(gl::UNIFORM_BUFFER, id);
+      gl::BufferSubData(
+         gl::UNIFORM_BUFFER,
+         0,
+         data.len() as GLsizeiptr,
+         data.as_ptr() as *const c_void,
+      );
+      gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+   }
+}
+
+pub(crate) fn bind_uniform_buffer_at(id: u32, binding: u32) {
+   unsafe {
+      gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, id);
+   }
+}
@@ -0,0 +1,147 @@
+use crate::{GLueError, Shader, Texture2D};
+use cgmath::{Matrix2, Matrix3, Matrix4, Vector2, Vector3, Vector4};
+use gl::types::{GLint, GLsizei};
+use kolor::RGBA;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MissingUniform {
+   Ignore,
+   Warn,
+   Error,
+}
+
+impl Default for MissingUniform {
+   fn default() -> MissingUniform {
+      MissingUniform::Warn
+   }
+}
+
+pub trait Uniform {
+   fn set_uniform(&self, shader: &mut Shader, location: GLint) -> Result<(), GLueError>;
+}
+
+// element types that can also be uploaded as a uniform array
+pub trait UniformArray: Sized {
+   fn set_uniform_array(values: &[Self], location: GLint);
+}
+
+macro_rules! uniform_vector {
+   ($typ:ty, $func:ident, $elem:ty) => {
+      impl UniformArray for $typ {
+         fn set_uniform_array(values: &[Self], location: GLint) {
+            unsafe {
+               gl::$func(
+                  location,
+                  values.len() as GLsizei,
+                  values.as_ptr() as *const $elem,
+               )
+            }
+         }
+      }
+      impl Uniform for $typ {
+         fn set_uniform(&self, _: &mut Shader, location: GLint) -> Result<(), GLueError> {
+            <$typ>::set_uniform_array(std::slice::from_ref(self), location);
+            Ok(())
+         }
+      }
+   };
+}
+
+macro_rules! uniform_matrix {
+   ($typ:ty, $func:ident) => {
+      impl UniformArray for $typ {
+         fn set_uniform_array(values: &[Self], location: GLint) {
+            unsafe {
+               gl::$func(
+                  location,
+                  values.len() as GLsizei,
+                  gl::FALSE,
+                  values.as_ptr() as *const f32,
+               )
+            }
+         }
+      }
+      impl Uniform for $typ {
+         fn set_uniform(&self, _: &mut Shader, location: GLint) -> Result<(), GLueError> {
+            <$typ>::set_uniform_array(std::slice::from_ref(self), location);
+            Ok(())
+         }
+      }
+   };
+}
+
+uniform_vector!(i32, Uniform1iv, i32);
+uniform_vector!(u32, Uniform1uiv, u32);
+uniform_vector!(f32, Uniform1fv, f32);
+uniform_vector!(Vector2<i32>, Uniform2iv, i32);
+uniform_vector!(Vector2<u32>, Uniform2uiv, u32);
+uniform_vector!(Vector2<f32>, Uniform2fv, f32);
+uniform_vector!(Vector3<i32>, Uniform3iv, i32);
+uniform_vector!(Vector3<u32>, Uniform3uiv, u32);
+uniform_vector!(Vector3<f32>, Uniform3fv, f32);
+uniform_vector!(Vector4<i32>, Uniform4iv, i32);
+uniform_vector!(Vector4<u32>, Uniform4uiv, u32);
+uniform_vector!(Vector4<f32>, Uniform4fv, f32);
+uniform_matrix!(Matrix2<f32>, UniformMatrix2fv);
+uniform_matrix!(Matrix3<f32>, UniformMatrix3fv);
+uniform_matrix!(Matrix4<f32>, UniformMatrix4fv);
+
+impl UniformArray for bool {
+   fn set_uniform_array(values: &[Self], location: GLint) {
+      let ints: Vec<i32> = values.iter().map(|b| *b as i32).collect();
+      i32::set_uniform_array(&ints, location)
+   }
+}
+impl Uniform for bool {
+   fn set_uniform(&self, _: &mut Shader, location: GLint) -> Result<(), GLueError> {
+      bool::set_uniform_array(std::slice::from_ref(self), location);
+      Ok(())
+   }
+}
+
+impl UniformArray for RGBA {
+   fn set_uniform_array(values: &[Self], location: GLint) {
+      let vecs: Vec<Vector4<f32>> = values
+         .iter()
+         .map(|c| Vector4::new(c.r(), c.g(), c.b(), c.a()))
+         .collect();
+      Vector4::<f32>::set_uniform_array(&vecs, location)
+   }
+}
+impl Uniform for RGBA {
+   fn set_uniform(&self, _: &mut Shader, location: GLint) -> Result<(), GLueError> {
+      RGBA::set_uniform_array(std::slice::from_ref(self), location);
+      Ok(())
+   }
+}
+
+impl<T: UniformArray, const N: usize> Uniform for [T; N] {
+   fn set_uniform(&self, _: &mut Shader, location: GLint) -> Result<(), GLueError> {
+      T::set_uniform_array(self, location);
+      Ok(())
+   }
+}
+impl<T: UniformArray> Uniform for &[T] {
+   fn set_uniform(&self, _: &mut Shader, location: GLint) -> Result<(), GLueError> {
+      T::set_uniform_array(self, location);
+      Ok(())
+   }
+}
+impl<T: UniformArray> Uniform for Vec<T> {
+   fn set_uniform(&self, _: &mut Shader, location: GLint) -> Result<(), GLueError> {
+      T::set_uniform_array(self, location);
+      Ok(())
+   }
+}
+
+// samplers (or images on compute) take a slot on the shader and point the uniform at it
+impl Uniform for &Texture2D {
+   fn set_uniform(&self, shader: &mut Shader, location: GLint) -> Result<(), GLueError> {
+      let slot = match shader.texture_slot(self) {
+         Err(e) => return Err(e),
+         Ok(slot) => slot,
+      };
+      i32::set_uniform_array(&[slot as i32], location);
+      Ok(())
+   }
+}
@@ -0,0 +1,560 @@
+use cgmath::{Matrix2, Matrix3, Matrix4, Vector2, Vector3, Vector4};
+use kolor::RGBA;
+
+// offset math shared by std140 and std430, the rules only differ in the alignments fed in
+pub struct BlockLayout;
+
+impl BlockLayout {
+   pub const fn round_up(n: usize, align: usize) -> usize {
+      match align {
+         0 => n,
+         _ => n.div_ceil(align) * align,
+      }
+   }
+   // the largest of aligns, never below min
+   pub const fn max_align(aligns: &[usize], min: usize) -> usize {
+      let mut align = min;
+      let mut i = 0;
+      while i < aligns.len() {
+         if aligns[i] > align {
+            align = aligns[i];
+         }
+         i += 1;
+      }
+      align
+   }
+   // fields are (align, size) in declaration order
+   pub const fn field_offset(fields: &[(usize, usize)], index: usize) -> usize {
+      let mut offset = 0;
+      let mut i = 0;
+      while i < index {
+         offset = BlockLayout::round_up(offset, fields[i].0) + fields[i].1;
+         i += 1;
+      }
+      BlockLayout::round_up(offset, fields[index].0)
+   }
+   pub const fn struct_size(fields: &[(usize, usize)], align: usize) -> usize {
+      match fields.len() {
+         0 => 0,
+         n => {
+            let last = BlockLayout::field_offset(fields, n - 1) + fields[n - 1].1;
+            BlockLayout::round_up(last, align)
+         }
+      }
+   }
+}
+
+// std140 rules: scalars align to 4, vec2 to 8, vec3/vec4 to 16, matrices are arrays of vec4 columns,
+// array elements and structs round their alignment and stride up to 16
+pub trait Std140 {
+   const ALIGN: usize;
+   const SIZE: usize;
+   fn write_std140(&self, w: &mut Std140Writer);
+}
+
+pub struct Std140Writer {
+   bytes: Vec<u8>,
+}
+
+impl Std140Writer {
+   pub fn new() -> Std140Writer {
+      Std140Writer { bytes: Vec::new() }
+   }
+
+   pub fn offset(&self) -> usize {
+      self.bytes.len()
+   }
+   pub fn align_to(&mut self, align: usize) {
+      let offset = BlockLayout::round_up(self.bytes.len(), align);
+      self.bytes.resize(offset, 0);
+   }
+   pub fn write<T: Std140>(&mut self, value: &T) {
+      self.align_to(T::ALIGN);
+      let end = self.bytes.len() + T::SIZE;
+      value.write_std140(self);
+      self.bytes.resize(end, 0);
+   }
+   pub fn write_bytes(&mut self, bytes: &[u8]) {
+      self.bytes.extend_from_slice(bytes);
+   }
+   pub fn into_bytes(self) -> Vec<u8> {
+      self.bytes
+   }
+
+   pub fn bytes_of<T: Std140>(value: &T) -> Vec<u8> {
+      let mut w = Std140Writer::new();
+      w.write(value);
+      w.into_bytes()
+   }
+}
+
+impl Default for Std140Writer {
+   fn default() -> Std140Writer {
+      Std140Writer::new()
+   }
+}
+
+macro_rules! std140_scalar {
+   ($typ:ty) => {
+      impl Std140 for $typ {
+         const ALIGN: usize = 4;
+         const SIZE: usize = 4;
+         fn write_std140(&self, w: &mut Std140Writer) {
+            w.write_bytes(&self.to_ne_bytes())
+         }
+      }
+   };
+}
+
+macro_rules! std140_vector {
+   ($vec:ident, $align:expr, $($c:ident),+) => {
+      impl<T: Std140> Std140 for $vec<T> {
+         const ALIGN: usize = $align;
+         const SIZE: usize = T::SIZE * [$(stringify!($c)),+].len();
+         fn write_std140(&self, w: &mut Std140Writer) {
+            $(self.$c.write_std140(w);)+
+         }
+      }
+   };
+}
+
+macro_rules! std140_matrix {
+   ($mat:ident, $cols:expr, $($c:ident),+) => {
+      impl Std140 for $mat<f32> {
+         const ALIGN: usize = 16;
+         const SIZE: usize = 16 * $cols;
+         fn write_std140(&self, w: &mut Std140Writer) {
+            $(
+               let end = w.offset() + 16;
+               self.$c.write_std140(w);
+               w.bytes.resize(end, 0);
+            )+
+         }
+      }
+   };
+}
+
+std140_scalar!(f32);
+std140_scalar!(i32);
+std140_scalar!(u32);
+std140_vector!(Vector2, 8, x, y);
+std140_vector!(Vector3, 16, x, y, z);
+std140_vector!(Vector4, 16, x, y, z, w);
+std140_matrix!(Matrix2, 2, x, y);
+std140_matrix!(Matrix3, 3, x, y, z);
+std140_matrix!(Matrix4, 4, x, y, z, w);
+
+impl Std140 for bool {
+   const ALIGN: usize = 4;
+   const SIZE: usize = 4;
+   fn write_std140(&self, w: &mut Std140Writer) {
+      (*self as u32).write_std140(w)
+   }
+}
+
+impl Std140 for RGBA {
+   const ALIGN: usize = 16;
+   const SIZE: usize = 16;
+   fn write_std140(&self, w: &mut Std140Writer) {
+      Vector4::new(self.r(), self.g(), self.b(), self.a()).write_std140(w)
+   }
+}
+
+impl<T: Std140, const N: usize> Std140 for [T; N] {
+   const ALIGN: usize = BlockLayout::round_up(T::ALIGN, 16);
+   const SIZE: usize = BlockLayout::round_up(T::SIZE, 16) * N;
+   fn write_std140(&self, w: &mut Std140Writer) {
+      let start = w.offset();
+      let stride = BlockLayout::round_up(T::SIZE, 16);
+      for (i, v) in self.iter().enumerate() {
+         w.align_to(16);
+         let end = start + stride * (i + 1);
+         v.write_std140(w);
+         w.bytes.resize(end, 0);
+      }
+   }
+}
+
+#[macro_export]
+macro_rules! std140_struct {
+   (
+      $(#[$meta:meta])*
+      $vis:vis struct $name:ident {
+         $($fvis:vis $field:ident : $typ:ty),* $(,)?
+      }
+   ) => {
+      $(#[$meta])*
+      $vis struct $name {
+         $($fvis $field: $typ),*
+      }
+
+      impl $name {
+         const STD140_FIELDS: &'static [(usize, usize)] =
+            &[$((<$typ as $crate::Std140>::ALIGN, <$typ as $crate::Std140>::SIZE)),*];
+
+         pub fn std140_offsets() -> Vec<(&'static str, usize)> {
+            let names: &[&'static str] = &[$(stringify!($field)),*];
+            names
+               .iter()
+               .enumerate()
+               .map(|(i, n)| (*n, $crate::BlockLayout::field_offset($name::STD140_FIELDS, i)))
+               .collect()
+         }
+      }
+
+      impl $crate::Std140 for $name {
+         const ALIGN: usize =
+            $crate::BlockLayout::max_align(&[$(<$typ as $crate::Std140>::ALIGN),*], 16);
+         const SIZE: usize =
+            $crate::BlockLayout::struct_size($name::STD140_FIELDS, <$name as $crate::Std140>::ALIGN);
+         fn write_std140(&self, w: &mut $crate::Std140Writer) {
+            $(w.write(&self.$field);)*
+         }
+      }
+   };
+}
+
+/// types that can be memcpy'd to and from the gpu
+///
+/// # Safety
+/// the type must have no padding bytes and every bit pattern must be a valid value of it,
+/// buffer contents are copied back into it as raw bytes
+pub unsafe trait GpuPod: Copy + 'static {}
+
+unsafe impl GpuPod for f32 {}
+unsafe impl GpuPod for i32 {}
+unsafe impl GpuPod for u32 {}
+unsafe impl GpuPod for u8 {}
+unsafe impl<T: GpuPod> GpuPod for Vector2<T> {}
+unsafe impl<T: GpuPod> GpuPod for Vector3<T> {}
+unsafe impl<T: GpuPod> GpuPod for Vector4<T> {}
+unsafe impl GpuPod for Matrix2<f32> {}
+unsafe impl GpuPod for Matrix3<f32> {}
+unsafe impl GpuPod for Matrix4<f32> {}
+unsafe impl<T: GpuPod, const N: usize> GpuPod for [T; N] {}
+
+// std430 rules: like std140 but arrays and structs keep their natural alignment
+pub trait Std430 {
+   const ALIGN: usize;
+   const SIZE: usize;
+   const STRIDE: usize = BlockLayout::round_up(Self::SIZE, Self::ALIGN);
+}
+
+macro_rules! std430_scalar {
+   ($typ:ty) => {
+      impl Std430 for $typ {
+         const ALIGN: usize = 4;
+         const SIZE: usize = 4;
+      }
+   };
+}
+
+std430_scalar!(f32);
+std430_scalar!(i32);
+std430_scalar!(u32);
+
+impl<T: Std430> Std430 for Vector2<T> {
+   const ALIGN: usize = T::ALIGN * 2;
+   const SIZE: usize = T::SIZE * 2;
+}
+impl<T: Std430> Std430 for Vector3<T> {
+   const ALIGN: usize = T::ALIGN * 4;
+   const SIZE: usize = T::SIZE * 3;
+}
+impl<T: Std430> Std430 for Vector4<T> {
+   const ALIGN: usize = T::ALIGN * 4;
+   const SIZE: usize = T::SIZE * 4;
+}
+impl Std430 for Matrix2<f32> {
+   const ALIGN: usize = 8;
+   const SIZE: usize = 16;
+}
+// no Matrix3, std430 pads each mat3 column to 16 bytes but cgmath packs them into 36,
+// use a Matrix4 or three Vector4 columns instead
+impl Std430 for Matrix4<f32> {
+   const ALIGN: usize = 16;
+   const SIZE: usize = 64;
+}
+impl<T: Std430, const N: usize> Std430 for [T; N] {
+   const ALIGN: usize = T::ALIGN;
+   const SIZE: usize = T::STRIDE * N;
+}
+
+// declares a #[repr(C)] struct and fails to compile if its rust layout differs from std430
+#[macro_export]
+macro_rules! std430_struct {
+   (
+      $(#[$meta:meta])*
+      $vis:vis struct $name:ident {
+         $($fvis:vis $field:ident : $typ:ty),* $(,)?
+      }
+   ) => {
+      $(#[$meta])*
+      #[repr(C)]
+      #[derive(Copy, Clone)]
+      $vis struct $name {
+         $($fvis $field: $typ),*
+      }
+
+      impl $name {
+         const STD430_FIELDS: &'static [(usize, usize)] =
+            &[$((<$typ as $crate::Std430>::ALIGN, <$typ as $crate::Std430>::SIZE)),*];
+
+         pub fn std430_offsets() -> Vec<(&'static str, usize)> {
+            let names: &[&'static str] = &[$(stringify!($field)),*];
+            names
+               .iter()
+               .enumerate()
+               .map(|(i, n)| (*n, $crate::BlockLayout::field_offset($name::STD430_FIELDS, i)))
+               .collect()
+         }
+      }
+
+      impl $crate::Std430 for $name {
+         const ALIGN: usize =
+            $crate::BlockLayout::max_align(&[$(<$typ as $crate::Std430>::ALIGN),*], 1);
+         const SIZE: usize =
+            $crate::BlockLayout::struct_size($name::STD430_FIELDS, <$name as $crate::Std430>::ALIGN);
+      }
+
+      unsafe impl $crate::GpuPod for $name where $($typ: $crate::GpuPod),* {}
+
+      const _: () = {
+         let mut _i = 0;
+         $(
+            assert!(
+               ::core::mem::offset_of!($name, $field)
+                  == $crate::BlockLayout::field_offset($name::STD430_FIELDS, _i),
+               concat!("std430 offset of ", stringify!($name), "::", stringify!($field), " does not match its rust offset, add padding"),
+            );
+            _i += 1;
+         )*
+         assert!(
+            ::core::mem::size_of::<$name>() == <$name as $crate::Std430>::STRIDE,
+            concat!("std430 stride of ", stringify!($name), " does not match its rust size, add padding"),
+         );
+      };
+   };
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use cgmath::{Matrix4, SquareMatrix};
+
+   crate::std140_struct! {
+      struct Block {
+         view: Matrix4<f32>,
+         pos: Vector3<f32>,
+         time: f32,
+         m3: Matrix3<f32>,
+         arr: [f32; 3],
+         v2: Vector2<f32>,
+         flag: bool,
+      }
+   }
+
+   crate::std140_struct! {
+      struct Light {
+         color: Vector3<f32>,
+         intensity: f32,
+      }
+   }
+
+   crate::std140_struct! {
+      struct Lights {
+         count: u32,
+         lights: [Light; 2],
+         ambient: f32,
+      }
+   }
+
+   crate::std430_struct! {
+      struct Particle {
+         pos: Vector3<f32>,
+         life: f32,
+         vel: Vector4<f32>,
+         weights: [f32; 3],
+         id: u32,
+      }
+   }
+
+   crate::std430_struct! {
+      struct Emitter {
+         origin: Vector4<f32>,
+         particle: Particle,
+         rate: f32,
+         _pad: [f32; 3],
+      }
+   }
+
+   fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+      f32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+   }
+
+   #[test]
+   fn std140_sizes() {
+      assert_eq!((<f32 as Std140>::ALIGN, <f32 as Std140>::SIZE), (4, 4));
+      assert_eq!(
+         (
+            <Vector2<f32> as Std140>::ALIGN,
+            <Vector2<f32> as Std140>::SIZE
+         ),
+         (8, 8)
+      );
+      assert_eq!(
+         (
+            <Vector3<f32> as Std140>::ALIGN,
+            <Vector3<f32> as Std140>::SIZE
+         ),
+         (16, 12)
+      );
+      assert_eq!(<Matrix3<f32> as Std140>::SIZE, 48);
+      assert_eq!(<Matrix2<f32> as Std140>::SIZE, 32);
+      assert_eq!(
+         (<[f32; 4] as Std140>::ALIGN, <[f32; 4] as Std140>::SIZE),
+         (16, 64)
+      );
+      assert_eq!(<[Vector3<f32>; 2] as Std140>::SIZE, 32);
+   }
+
+   #[test]
+   fn std140_struct_offsets() {
+      let offsets = Block::std140_offsets();
+      assert_eq!(
+         offsets,
+         [
+            ("view", 0),
+            ("pos", 64),
+            ("time", 76),
+            ("m3", 80),
+            ("arr", 128),
+            ("v2", 176),
+            ("flag", 184),
+         ]
+      );
+      assert_eq!(<Block as Std140>::ALIGN, 16);
+      assert_eq!(<Block as Std140>::SIZE, 192);
+   }
+
+   #[test]
+   fn std140_nested_struct_offsets() {
+      assert_eq!(Light::std140_offsets(), [("color", 0), ("intensity", 12)]);
+      assert_eq!(<Light as Std140>::SIZE, 16);
+      assert_eq!(
+         Lights::std140_offsets(),
+         [("count", 0), ("lights", 16), ("ambient", 48)]
+      );
+      assert_eq!(<Lights as Std140>::SIZE, 64);
+   }
+
+   #[test]
+   fn std140_writer_bytes() {
+      let block = Block {
+         view: Matrix4::identity(),
+         pos: Vector3::new(1.0, 2.0, 3.0),
+         time: 4.0,
+         m3: Matrix3::new(1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0),
+         arr: [10.0, 11.0, 12.0],
+         v2: Vector2::new(13.0, 14.0),
+         flag: true,
+      };
+      let bytes = Std140Writer::bytes_of(&block);
+      assert_eq!(bytes.len(), 192);
+      assert_eq!(f32_at(&bytes, 0), 1.0);
+      assert_eq!(f32_at(&bytes, 20), 1.0);
+      assert_eq!(f32_at(&bytes, 72), 3.0);
+      assert_eq!(f32_at(&bytes, 76), 4.0);
+      // mat3 columns are padded to vec4
+      assert_eq!(f32_at(&bytes, 88), 3.0);
+      assert_eq!(f32_at(&bytes, 92), 0.0);
+      assert_eq!(f32_at(&bytes, 96), 4.0);
+      assert_eq!(f32_at(&bytes, 112), 7.0);
+      // scalar array elements have a 16 byte stride
+      assert_eq!(f32_at(&bytes, 128), 10.0);
+      assert_eq!(f32_at(&bytes, 144), 11.0);
+      assert_eq!(f32_at(&bytes, 160), 12.0);
+      assert_eq!(f32_at(&bytes, 180), 14.0);
+      assert_eq!(u32::from_ne_bytes(bytes[184..188].try_into().unwrap()), 1);
+   }
+
+   #[test]
+   fn std140_writer_nested_bytes() {
+      let light = |v| Light {
+         color: Vector3::new(v, v, v),
+         intensity: v + 0.5,
+      };
+      let lights = Lights {
+         count: 2,
+         lights: [light(1.0), light(2.0)],
+         ambient: 0.25,
+      };
+      let bytes = Std140Writer::bytes_of(&lights);
+      assert_eq!(bytes.len(), 64);
+      assert_eq!(u32::from_ne_bytes(bytes[0..4].try_into().unwrap()), 2);
+      assert_eq!(f32_at(&bytes, 16), 1.0);
+      assert_eq!(f32_at(&bytes, 28), 1.5);
+      assert_eq!(f32_at(&bytes, 32), 2.0);
+      assert_eq!(f32_at(&bytes, 44), 2.5);
+      assert_eq!(f32_at(&bytes, 48), 0.25);
+   }
+
+   #[test]
+   fn std140_writer_alignment() {
+      let mut w = Std140Writer::new();
+      w.write(&1.0f32);
+      w.write(&Vector3::new(1.0f32, 2.0, 3.0));
+      assert_eq!(w.offset(), 28);
+      w.write(&2.0f32);
+      assert_eq!(w.offset(), 32);
+      w.write(&Vector2::new(1.0f32, 2.0));
+      w.write(&3.0f32);
+      assert_eq!(w.offset(), 44);
+      assert_eq!(BlockLayout::round_up(13, 16), 16);
+      assert_eq!(BlockLayout::round_up(13, 0), 13);
+   }
+
+   #[test]
+   fn std430_sizes() {
+      assert_eq!(
+         (<[f32; 4] as Std430>::ALIGN, <[f32; 4] as Std430>::SIZE),
+         (4, 16)
+      );
+      assert_eq!(<[Vector3<f32>; 2] as Std430>::SIZE, 32);
+      assert_eq!(<[Vector2<f32>; 3] as Std430>::SIZE, 24);
+      assert_eq!(
+         (
+            <Vector3<f32> as Std430>::ALIGN,
+            <Vector3<f32> as Std430>::STRIDE
+         ),
+         (16, 16)
+      );
+   }
+
+   #[test]
+   fn std430_struct_offsets() {
+      assert_eq!(
+         Particle::std430_offsets(),
+         [
+            ("pos", 0),
+            ("life", 12),
+            ("vel", 16),
+            ("weights", 32),
+            ("id", 44)
+         ]
+      );
+      assert_eq!(<Particle as Std430>::ALIGN, 16);
+      assert_eq!(<Particle as Std430>::STRIDE, 48);
+      assert_eq!(std::mem::size_of::<Particle>(), 48);
+   }
+
+   #[test]
+   fn std430_nested_struct_offsets() {
+      assert_eq!(
+         Emitter::std430_offsets(),
+         [("origin", 0), ("particle", 16), ("rate", 64), ("_pad", 68)]
+      );
+      assert_eq!(<Emitter as Std430>::STRIDE, 80);
+      assert_eq!(std::mem::size_of::<Emitter>(), 80);
+   }
+}
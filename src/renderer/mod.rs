@@ -1,11 +1,25 @@
 mod camera;
-mod glraw;
+mod caps;
+mod config;
+mod debug;
+mod device;
+pub(crate) mod glraw;
 mod handles;
+mod layout;
 mod renderer;
+mod state;
+mod timer;
 mod util;
 
 pub use camera::*;
+pub use caps::*;
+pub use config::*;
+pub use debug::*;
+pub use device::*;
 pub use core::*;
 pub use handles::*;
+pub use layout::*;
 pub use renderer::*;
+pub use state::*;
+pub use timer::*;
 pub use util::*;
@@ -1,27 +1,92 @@
-use crate::renderer::glraw::GL;
-use crate::{CamProj, Camera, Size2D};
+use crate::asset::{read_framebuffer, read_framebuffer_async};
+use crate::renderer::glraw::{Ctx, GL};
+use crate::renderer::{
+   DebugState, bind_framebuffer, collect_garbage, disable_debug_output, drain_warnings,
+   enable_debug_output, is_tracking_objects, live_objects, track_objects, unbind_framebuffer,
+};
+use crate::{
+   CamProj, Camera, Caps, DebugMessage, DebugSeverity, Device, Drawable, GpuConfig, Image,
+   ImgFormat, LiveObject, PendingImage, RenderState, RenderTarget, ShaderDiagnostic, Size2D,
+};
 use kolor::RGBA;
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub enum PolyMode {
    Points,
    WireFrame,
    Filled,
 }
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Cull {
    Clock,
    AntiClock,
 }
 
-#[derive(Copy, Clone, Debug)]
-pub(crate) enum ShaderSrcType {
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ShaderSrcType {
    Vert,
+   TessCtrl,
+   TessEval,
+   Geom,
    Frag,
    Compute,
 }
 
-#[derive(Debug)]
+impl ShaderSrcType {
+   pub(crate) const PIPELINE: [ShaderSrcType; 5] = [
+      ShaderSrcType::Vert,
+      ShaderSrcType::TessCtrl,
+      ShaderSrcType::TessEval,
+      ShaderSrcType::Geom,
+      ShaderSrcType::Frag,
+   ];
+
+   pub fn as_str(&self) -> &str {
+      match self {
+         ShaderSrcType::Vert => "vertex",
+         ShaderSrcType::TessCtrl => "tessellation control",
+         ShaderSrcType::TessEval => "tessellation evaluation",
+         ShaderSrcType::Geom => "geometry",
+         ShaderSrcType::Frag => "fragment",
+         ShaderSrcType::Compute => "compute",
+      }
+   }
+   pub fn ex(&self) -> &'static str {
+      match self {
+         ShaderSrcType::Vert => "vert",
+         ShaderSrcType::TessCtrl => "tesc",
+         ShaderSrcType::TessEval => "tese",
+         ShaderSrcType::Geom => "geom",
+         ShaderSrcType::Frag => "frag",
+         ShaderSrcType::Compute => "comp",
+      }
+   }
+   pub fn from_ex(ex: &str) -> Option<ShaderSrcType> {
+      match ex.to_lowercase().as_str() {
+         "vert" => Some(ShaderSrcType::Vert),
+         "tesc" => Some(ShaderSrcType::TessCtrl),
+         "tese" => Some(ShaderSrcType::TessEval),
+         "geom" => Some(ShaderSrcType::Geom),
+         "frag" => Some(ShaderSrcType::Frag),
+         "comp" => Some(ShaderSrcType::Compute),
+         _ => None,
+      }
+   }
+   pub(crate) fn order(&self) -> usize {
+      match self {
+         ShaderSrcType::Compute => 0,
+         typ => ShaderSrcType::PIPELINE
+            .iter()
+            .position(|t| t == typ)
+            .unwrap_or(0),
+      }
+   }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum GLueErrorKind {
    //UNKNOWN
    SomethingWentWrong,
@@ -34,13 +99,22 @@ pub enum GLueErrorKind {
    MakeContextFailed,
    MakeCurrentFailed,
    NoVersion,
+   UnsupportedVersion,
+   Unsupported,
    NoDevice,
+   GLDebug,
    //SHADERS
    ShaderCompileFailed,
    ProgramLinkFailed,
    MissingSrc,
+   MissingInclude,
+   IncludeCycle,
+   MissingUniform,
+   LayoutMismatch,
    //MESHES
    NotTriangle,
+   //TARGETS
+   IncompleteTarget,
    //FILE IO
    Missing,
    NoPerms,
@@ -50,7 +124,7 @@ pub enum GLueErrorKind {
 }
 
 impl GLueErrorKind {
-   fn as_str(&self) -> &str {
+   pub fn as_str(&self) -> &str {
       match self {
          // UNKNOWN
          GLueErrorKind::SomethingWentWrong => "unknown",
@@ -64,16 +138,26 @@ impl GLueErrorKind {
          | GLueErrorKind::MakeContextFailed
          | GLueErrorKind::MakeCurrentFailed
          | GLueErrorKind::NoVersion
-         | GLueErrorKind::NoDevice => "opengl",
+         | GLueErrorKind::UnsupportedVersion
+         | GLueErrorKind::Unsupported
+         | GLueErrorKind::NoDevice
+         | GLueErrorKind::GLDebug => "opengl",
 
          // SHADERS
          GLueErrorKind::ShaderCompileFailed
          | GLueErrorKind::ProgramLinkFailed
-         | GLueErrorKind::MissingSrc => "shader",
+         | GLueErrorKind::MissingSrc
+         | GLueErrorKind::MissingInclude
+         | GLueErrorKind::IncludeCycle
+         | GLueErrorKind::MissingUniform
+         | GLueErrorKind::LayoutMismatch => "shader",
 
          // MESHES
          GLueErrorKind::NotTriangle => "mesh",
 
+         // TARGETS
+         GLueErrorKind::IncompleteTarget => "target",
+
          // FILE IO
          GLueErrorKind::Missing
          | GLueErrorKind::NoPerms
@@ -87,22 +171,51 @@ impl GLueErrorKind {
 pub struct GLueError {
    msg: String,
    kind: GLueErrorKind,
+   path: Option<String>,
+   source: Option<Box<dyn Error + Send + Sync + 'static>>,
+   diagnostics: Vec<ShaderDiagnostic>,
 }
 
 impl GLueError {
    pub fn wtf(msg: &str) -> Self {
-      GLueError {
-         msg: msg.to_string(),
-         kind: GLueErrorKind::SomethingWentWrong,
-      }
+      GLueError::from(GLueErrorKind::SomethingWentWrong, msg)
    }
    pub fn from(kind: GLueErrorKind, msg: &str) -> Self {
       GLueError {
          msg: msg.to_string(),
          kind,
+         path: None,
+         source: None,
+         diagnostics: Vec::new(),
       }
    }
+   pub fn with_path(mut self, path: &str) -> Self {
+      self.path = Some(path.to_string());
+      self
+   }
+   pub fn with_source<E: Error + Send + Sync + 'static>(mut self, source: E) -> Self {
+      self.source = Some(Box::new(source));
+      self
+   }
+
+   pub(crate) fn with_diagnostics(mut self, diagnostics: Vec<ShaderDiagnostic>) -> Self {
+      self.diagnostics = diagnostics;
+      self
+   }
+
+   pub fn kind(&self) -> GLueErrorKind {
+      self.kind
+   }
+   pub fn path(&self) -> Option<&str> {
+      self.path.as_deref()
+   }
+   pub fn diagnostics(&self) -> &[ShaderDiagnostic] {
+      &self.diagnostics
+   }
    pub fn msg(&self) -> String {
+      self.to_string()
+   }
+   pub fn colored_msg(&self) -> String {
       format!(
          "\x1b[1;31mGLUE ERROR ({}):\x1b[0m \x1b[31m{}\x1b[0m",
          self.kind.as_str(),
@@ -111,41 +224,80 @@ impl GLueError {
    }
 }
 
+impl fmt::Display for GLueError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      write!(f, "GLUE ERROR ({}): {}", self.kind.as_str(), self.msg)
+   }
+}
+
+impl Error for GLueError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match &self.source {
+         None => None,
+         Some(e) => Some(e.as_ref()),
+      }
+   }
+}
+
 pub struct GPU {
    pub(crate) gl: GL,
+   pub(crate) debug: Option<Box<RefCell<DebugState>>>,
+   pub(crate) config: GpuConfig,
    pub(crate) cam: Camera,
    pub(crate) poly_mode: PolyMode,
-   pub(crate) cull_face: Cull,
+   pub(crate) state: RenderState,
    pub(crate) bg_color: RGBA,
    pub(crate) msaa: bool,
    pub(crate) msaa_samples: u32,
-   pub(crate) culling: bool,
 }
 
 impl GPU {
    pub fn load() -> Result<GPU, GLueError> {
-      let cam = Camera::new(Size2D::from(10, 10), CamProj::Ortho);
+      GPU::load_with(GpuConfig::default())
+   }
+   pub fn load_sized(size: Size2D) -> Result<GPU, GLueError> {
+      GPU::load_with(GpuConfig::default().with_size(size))
+   }
+   pub fn load_on(device: Device) -> Result<GPU, GLueError> {
+      GPU::load_with(GpuConfig::default().with_device(device))
+   }
+   pub fn devices() -> Result<Vec<Device>, GLueError> {
+      Device::enumerate()
+   }
+   pub fn load_with(config: GpuConfig) -> Result<GPU, GLueError> {
+      let size = config.size;
+      let mut cam = Camera::new(size, CamProj::Ortho);
+      cam.start();
       let bg_color = RGBA::grey(0.5);
-      let gl = match GL::load(10, 10) {
+      let gl = match GL::load(&config) {
          Err(e) => return Err(e),
          Ok(gl) => gl,
       };
 
+      let debug = config.debug;
       let mut renderer = GPU {
          gl,
+         debug: None,
+         config,
          cam,
          bg_color,
          msaa: true,
-         culling: true,
          msaa_samples: 4,
-         cull_face: Cull::AntiClock,
+         state: RenderState::default(),
          poly_mode: PolyMode::Filled,
       };
       renderer.set_msaa(true);
-      renderer.set_culling(true);
+      renderer.set_render_state(RenderState::default());
       renderer.set_wire_width(2.0);
       renderer.set_bg_color(bg_color);
-      renderer.gl.enable_alpha(true);
+      renderer.gl.set_viewport(size);
+      if debug {
+         match renderer.enable_debug(DebugSeverity::Low) {
+            Err(e) => return Err(e),
+            Ok(_) => {}
+         }
+         renderer.track_objects(true);
+      }
       Ok(renderer)
    }
    pub fn version(&self) -> &str {
@@ -157,14 +309,200 @@ impl GPU {
    pub fn name(&self) -> &str {
       &self.gl.device
    }
+   pub fn vendor(&self) -> &str {
+      &self.gl.vendor
+   }
+   pub fn is_surfaceless(&self) -> bool {
+      self.gl.surface.is_none()
+   }
+   pub fn version_number(&self) -> (u8, u8) {
+      (self.gl.major, self.gl.minor)
+   }
+   pub fn config(&self) -> &GpuConfig {
+      &self.config
+   }
+   pub fn caps(&self) -> Caps {
+      Caps::query()
+   }
+
+   pub fn read_pixels(&self, fmt: ImgFormat) -> Result<Vec<u8>, GLueError> {
+      match self.snapshot_as(fmt) {
+         Err(e) => Err(e),
+         Ok(img) => Ok(img.bytes),
+      }
+   }
+   pub fn snapshot(&self) -> Result<Image, GLueError> {
+      self.snapshot_as(ImgFormat::RGBA(8))
+   }
+   // always reads the main surface at its own size, render targets have their own snapshots
+   pub fn snapshot_as(&self, fmt: ImgFormat) -> Result<Image, GLueError> {
+      let size = self.gl.size;
+      let bytes = match &self.gl.main {
+         Some(main) if main.is_msaa() => {
+            main.resolve();
+            bind_framebuffer(main.fbo.id);
+            let bytes = read_framebuffer(size, &fmt);
+            unbind_framebuffer();
+            bytes
+         }
+         _ => {
+            unbind_framebuffer();
+            read_framebuffer(size, &fmt)
+         }
+      };
+      match bytes {
+         Err(e) => Err(e),
+         Ok(bytes) => Ok(Image::from_bytes(bytes, size, fmt)),
+      }
+   }
 
-   pub(crate) fn set_size(&mut self, size: Size2D) {
-      self.gl.resize(size);
+   pub fn snapshot_async(&self) -> Result<PendingImage, GLueError> {
+      self.snapshot_async_as(ImgFormat::RGBA(8))
+   }
+   pub fn snapshot_async_as(&self, fmt: ImgFormat) -> Result<PendingImage, GLueError> {
+      let size = self.gl.size;
+      match &self.gl.main {
+         Some(main) if main.is_msaa() => {
+            main.resolve();
+            bind_framebuffer(main.fbo.id);
+            let pending = read_framebuffer_async(size, &fmt);
+            unbind_framebuffer();
+            pending
+         }
+         _ => {
+            unbind_framebuffer();
+            read_framebuffer_async(size, &fmt)
+         }
+      }
+   }
+
+   pub fn size(&self) -> Size2D {
+      self.gl.size
+   }
+   pub fn set_size(&mut self, size: Size2D) -> Result<(), GLueError> {
+      match self.gl.resize(size) {
+         Err(e) => return Err(e),
+         Ok(_) => {}
+      }
+      self.cam.set_size(size);
+      Ok(())
+   }
+   pub fn set_target(&self, target: Option<&RenderTarget>) {
+      match target {
+         Some(t) => t.bind(),
+         None => {
+            unbind_framebuffer();
+            self.gl.set_viewport(self.size());
+         }
+      }
+   }
+
+   pub fn enable_debug(&mut self, min_severity: DebugSeverity) -> Result<(), GLueError> {
+      match &self.debug {
+         Some(state) => {
+            state.borrow_mut().min_severity = min_severity;
+            return Ok(());
+         }
+         None => {}
+      }
+      let state = Box::new(RefCell::new(DebugState::new(min_severity)));
+      match enable_debug_output(&state) {
+         Err(e) => return Err(e),
+         Ok(_) => {}
+      }
+      self.debug = Some(state);
+      Ok(())
+   }
+   pub fn disable_debug(&mut self) {
+      if self.debug.is_some() {
+         disable_debug_output();
+         self.debug = None;
+      }
+   }
+   pub fn is_debug(&self) -> bool {
+      self.debug.is_some()
+   }
+   // turns debug output on at the default severity if it is off
+   pub fn set_debug_sink<F: FnMut(&DebugMessage) + 'static>(
+      &mut self,
+      sink: F,
+   ) -> Result<(), GLueError> {
+      if self.debug.is_none() {
+         match self.enable_debug(DebugSeverity::Low) {
+            Err(e) => return Err(e),
+            Ok(_) => {}
+         }
+      }
+      match &self.debug {
+         None => {}
+         Some(state) => state.borrow_mut().sink = Some(Box::new(sink)),
+      }
+      Ok(())
    }
-   fn clear(&self) {
+   pub fn remove_debug_sink(&mut self) {
+      match &self.debug {
+         None => {}
+         Some(state) => state.borrow_mut().sink = None,
+      }
+   }
+   pub fn drain_debug_errors(&mut self) -> Vec<GLueError> {
+      match &self.debug {
+         None => Vec::new(),
+         Some(state) => state.borrow_mut().errors.drain(..).collect(),
+      }
+   }
+   // library warnings (missing uniforms under MissingUniform::Warn) raised while debug output
+   // was off, with it on they arrive through the sink and drain_debug_errors
+   pub fn drain_warnings(&mut self) -> Vec<GLueError> {
+      drain_warnings()
+   }
+
+   pub fn cam(&self) -> &Camera {
+      &self.cam
+   }
+   pub fn cam_mut(&mut self) -> &mut Camera {
+      &mut self.cam
+   }
+
+   pub fn begin_frame(&mut self) {
+      self.set_target(None);
+      self.cam.set_size(self.size());
+      self.cam.pre_update();
+      self.cam.update();
+      self.clear();
+   }
+   pub fn end_frame(&mut self) {
+      self.cam.post_update();
+      self.collect_garbage();
+      unsafe {
+         gl::Flush();
+      }
+   }
+   pub fn draw<D: Drawable>(&self, drawable: &D) -> Result<(), GLueError> {
+      drawable.render_with(&self.cam)
+   }
+
+   pub fn clear(&self) {
       self.gl.clear()
    }
 
+   pub fn collect_garbage(&self) -> usize {
+      collect_garbage()
+   }
+   pub fn track_objects(&mut self, enable: bool) {
+      track_objects(enable)
+   }
+   pub fn is_tracking_objects(&self) -> bool {
+      is_tracking_objects()
+   }
+   pub fn live_objects(&self) -> Vec<LiveObject> {
+      live_objects()
+   }
+   pub fn shutdown(self) -> Vec<LiveObject> {
+      self.collect_garbage();
+      self.live_objects()
+   }
+
    pub fn set_msaa_samples(&mut self, samples: u32) {
       self.msaa_samples = samples
    }
@@ -172,16 +510,26 @@ impl GPU {
       self.bg_color = color;
       self.gl.set_clear(color);
    }
-   pub fn set_poly_mode(&mut self, mode: PolyMode) {
+   pub fn set_poly_mode(&mut self, mode: PolyMode) -> Result<(), GLueError> {
+      match (mode, Ctx::current().is_es()) {
+         (PolyMode::Filled, _) | (_, false) => {}
+         (mode, true) => {
+            return Err(GLueError::from(
+               GLueErrorKind::Unsupported,
+               &format!("{mode:?} poly mode is unsupported on opengl es"),
+            ));
+         }
+      }
       self.poly_mode = mode;
       self.gl.poly_mode(mode);
+      Ok(())
    }
-   pub fn toggle_wireframe(&mut self) {
+   pub fn toggle_wireframe(&mut self) -> Result<(), GLueError> {
       let new_poly_mode = match self.poly_mode {
          PolyMode::WireFrame => PolyMode::Filled,
          _ => PolyMode::WireFrame,
       };
-      self.set_poly_mode(new_poly_mode);
+      self.set_poly_mode(new_poly_mode)
    }
    pub fn set_msaa(&mut self, enable: bool) {
       self.msaa = enable;
@@ -194,26 +542,28 @@ impl GPU {
       self.msaa = !self.msaa;
       self.gl.enable_msaa(self.msaa)
    }
+   pub fn render_state(&self) -> RenderState {
+      self.state
+   }
+   pub fn set_render_state(&mut self, state: RenderState) {
+      self.state = state;
+      state.set_base();
+   }
    pub fn set_culling(&mut self, enable: bool) {
-      if self.culling != enable {
-         self.toggle_culling()
-      }
-      self.gl.enable_cull(enable);
+      self.set_render_state(self.state.with_culling(enable))
    }
    pub fn toggle_culling(&mut self) {
-      self.culling = !self.culling;
-      self.gl.enable_cull(self.culling);
+      self.set_culling(!self.state.culling)
    }
    pub fn set_cull_face(&mut self, cull_face: Cull) {
-      self.cull_face = cull_face;
-      self.gl.set_cull_face(self.cull_face)
+      self.set_render_state(self.state.with_cull_face(cull_face))
    }
    pub fn flip_cull_face(&mut self) {
-      self.cull_face = match self.cull_face {
+      let cull_face = match self.state.cull_face {
          Cull::Clock => Cull::AntiClock,
          Cull::AntiClock => Cull::Clock,
       };
-      self.gl.set_cull_face(self.cull_face);
+      self.set_cull_face(cull_face)
    }
    pub fn set_wire_width(&mut self, width: f32) {
       self.gl.set_wire_width(width);
@@ -0,0 +1,325 @@
+use crate::{Cull, Size2D};
+use gl::types::{GLenum, GLsizei};
+use std::cell::Cell;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CompareFunc {
+   Never,
+   Less,
+   Equal,
+   LessEqual,
+   Greater,
+   NotEqual,
+   GreaterEqual,
+   Always,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlendMode {
+   Off,
+   Alpha,
+   Premultiplied,
+   Additive,
+   Multiply,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StencilOp {
+   Keep,
+   Zero,
+   Replace,
+   Incr,
+   IncrWrap,
+   Decr,
+   DecrWrap,
+   Invert,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StencilState {
+   pub func: CompareFunc,
+   pub reference: i32,
+   pub read_mask: u32,
+   pub write_mask: u32,
+   pub fail: StencilOp,
+   pub depth_fail: StencilOp,
+   pub pass: StencilOp,
+}
+
+impl Default for StencilState {
+   fn default() -> StencilState {
+      StencilState {
+         func: CompareFunc::Always,
+         reference: 0,
+         read_mask: 0xFF,
+         write_mask: 0xFF,
+         fail: StencilOp::Keep,
+         depth_fail: StencilOp::Keep,
+         pass: StencilOp::Keep,
+      }
+   }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScissorRect {
+   pub x: i32,
+   pub y: i32,
+   pub size: Size2D,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderState {
+   pub(crate) depth_test: bool,
+   pub(crate) depth_func: CompareFunc,
+   pub(crate) depth_write: bool,
+   pub(crate) blend: BlendMode,
+   pub(crate) stencil: Option<StencilState>,
+   pub(crate) scissor: Option<ScissorRect>,
+   pub(crate) color_mask: [bool; 4],
+   pub(crate) culling: bool,
+   pub(crate) cull_face: Cull,
+}
+
+impl Default for RenderState {
+   fn default() -> RenderState {
+      RenderState {
+         depth_test: false,
+         depth_func: CompareFunc::Less,
+         depth_write: true,
+         blend: BlendMode::Alpha,
+         stencil: None,
+         scissor: None,
+         color_mask: [true; 4],
+         culling: true,
+         cull_face: Cull::AntiClock,
+      }
+   }
+}
+
+thread_local! {
+   static BASE_STATE: Cell<Option<RenderState>> = const { Cell::new(None) };
+   static APPLIED_STATE: Cell<Option<RenderState>> = const { Cell::new(None) };
+}
+
+impl RenderState {
+   pub fn new() -> RenderState {
+      RenderState::default()
+   }
+
+   pub fn with_depth_test(mut self, enable: bool) -> RenderState {
+      self.depth_test = enable;
+      self
+   }
+   pub fn with_depth_func(mut self, func: CompareFunc) -> RenderState {
+      self.depth_func = func;
+      self
+   }
+   pub fn with_depth_write(mut self, enable: bool) -> RenderState {
+      self.depth_write = enable;
+      self
+   }
+   pub fn with_blend(mut self, blend: BlendMode) -> RenderState {
+      self.blend = blend;
+      self
+   }
+   pub fn with_stencil(mut self, stencil: Option<StencilState>) -> RenderState {
+      self.stencil = stencil;
+      self
+   }
+   pub fn with_scissor(mut self, scissor: Option<ScissorRect>) -> RenderState {
+      self.scissor = scissor;
+      self
+   }
+   pub fn with_color_mask(mut self, r: bool, g: bool, b: bool, a: bool) -> RenderState {
+      self.color_mask = [r, g, b, a];
+      self
+   }
+   pub fn with_culling(mut self, enable: bool) -> RenderState {
+      self.culling = enable;
+      self
+   }
+   pub fn with_cull_face(mut self, cull_face: Cull) -> RenderState {
+      self.cull_face = cull_face;
+      self
+   }
+
+   pub fn depth_test(&self) -> bool {
+      self.depth_test
+   }
+   pub fn depth_func(&self) -> CompareFunc {
+      self.depth_func
+   }
+   pub fn depth_write(&self) -> bool {
+      self.depth_write
+   }
+   pub fn blend(&self) -> BlendMode {
+      self.blend
+   }
+   pub fn stencil(&self) -> Option<StencilState> {
+      self.stencil
+   }
+   pub fn scissor(&self) -> Option<ScissorRect> {
+      self.scissor
+   }
+   pub fn color_mask(&self) -> [bool; 4] {
+      self.color_mask
+   }
+   pub fn culling(&self) -> bool {
+      self.culling
+   }
+   pub fn cull_face(&self) -> Cull {
+      self.cull_face
+   }
+
+   pub(crate) fn base() -> RenderState {
+      BASE_STATE.with(|s| s.get()).unwrap_or_default()
+   }
+   // clears and blits obey the write masks and scissor, so they run with those reset
+   pub(crate) fn for_clear() -> RenderState {
+      RenderState {
+         depth_write: true,
+         stencil: None,
+         scissor: None,
+         color_mask: [true; 4],
+         ..RenderState::base()
+      }
+   }
+   pub(crate) fn set_base(self) {
+      BASE_STATE.with(|s| s.set(Some(self)));
+      self.apply();
+   }
+   pub(crate) fn reset_cache() {
+      BASE_STATE.with(|s| s.set(None));
+      APPLIED_STATE.with(|s| s.set(None));
+   }
+
+   pub(crate) fn apply(&self) {
+      let prev = APPLIED_STATE.with(|s| s.get());
+      if prev == Some(*self) {
+         return;
+      }
+      let changed = |same: fn(&RenderState, &RenderState) -> bool| match &prev {
+         None => true,
+         Some(p) => !same(p, self),
+      };
+      unsafe {
+         if changed(|a, b| a.depth_test == b.depth_test) {
+            enable_cap(gl::DEPTH_TEST, self.depth_test);
+         }
+         if changed(|a, b| a.depth_func == b.depth_func) {
+            gl::DepthFunc(match_compare_func(&self.depth_func));
+         }
+         if changed(|a, b| a.depth_write == b.depth_write) {
+            gl::DepthMask(match_bool(self.depth_write));
+         }
+         if changed(|a, b| a.blend == b.blend) {
+            match match_blend_funcs(&self.blend) {
+               None => enable_cap(gl::BLEND, false),
+               Some((src, dst)) => {
+                  enable_cap(gl::BLEND, true);
+                  gl::BlendFunc(src, dst);
+               }
+            }
+         }
+         if changed(|a, b| a.stencil == b.stencil) {
+            match &self.stencil {
+               None => {
+                  enable_cap(gl::STENCIL_TEST, false);
+                  gl::StencilMask(!0);
+               }
+               Some(st) => {
+                  enable_cap(gl::STENCIL_TEST, true);
+                  gl::StencilFunc(match_compare_func(&st.func), st.reference, st.read_mask);
+                  gl::StencilOp(
+                     match_stencil_op(&st.fail),
+                     match_stencil_op(&st.depth_fail),
+                     match_stencil_op(&st.pass),
+                  );
+                  gl::StencilMask(st.write_mask);
+               }
+            }
+         }
+         if changed(|a, b| a.scissor == b.scissor) {
+            match &self.scissor {
+               None => enable_cap(gl::SCISSOR_TEST, false),
+               Some(rect) => {
+                  enable_cap(gl::SCISSOR_TEST, true);
+                  gl::Scissor(
+                     rect.x,
+                     rect.y,
+                     rect.size.w as GLsizei,
+                     rect.size.h as GLsizei,
+                  );
+               }
+            }
+         }
+         if changed(|a, b| a.color_mask == b.color_mask) {
+            let [r, g, b, a] = self.color_mask;
+            gl::ColorMask(match_bool(r), match_bool(g), match_bool(b), match_bool(a));
+         }
+         if changed(|a, b| a.culling == b.culling) {
+            enable_cap(gl::CULL_FACE, self.culling);
+            gl::CullFace(gl::BACK);
+         }
+         if changed(|a, b| a.cull_face == b.cull_face) {
+            match self.cull_face {
+               Cull::Clock => gl::FrontFace(gl::CW),
+               Cull::AntiClock => gl::FrontFace(gl::CCW),
+            }
+         }
+      }
+      APPLIED_STATE.with(|s| s.set(Some(*self)));
+   }
+}
+
+unsafe fn enable_cap(cap: GLenum, enable: bool) {
+   unsafe {
+      match enable {
+         true => gl::Enable(cap),
+         false => gl::Disable(cap),
+      }
+   }
+}
+
+fn match_bool(b: bool) -> u8 {
+   match b {
+      true => gl::TRUE,
+      false => gl::FALSE,
+   }
+}
+
+fn match_compare_func(func: &CompareFunc) -> GLenum {
+   match func {
+      CompareFunc::Never => gl::NEVER,
+      CompareFunc::Less => gl::LESS,
+      CompareFunc::Equal => gl::EQUAL,
+      CompareFunc::LessEqual => gl::LEQUAL,
+      CompareFunc::Greater => gl::GREATER,
+      CompareFunc::NotEqual => gl::NOTEQUAL,
+      CompareFunc::GreaterEqual => gl::GEQUAL,
+      CompareFunc::Always => gl::ALWAYS,
+   }
+}
+
+fn match_blend_funcs(blend: &BlendMode) -> Option<(GLenum, GLenum)> {
+   match blend {
+      BlendMode::Off => None,
+      BlendMode::Alpha => Some((gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA)),
+      BlendMode::Premultiplied => Some((gl::ONE, gl::ONE_MINUS_SRC_ALPHA)),
+      BlendMode::Additive => Some((gl::SRC_ALPHA, gl::ONE)),
+      BlendMode::Multiply => Some((gl::DST_COLOR, gl::ZERO)),
+   }
+}
+
+fn match_stencil_op(op: &StencilOp) -> GLenum {
+   match op {
+      StencilOp::Keep => gl::KEEP,
+      StencilOp::Zero => gl::ZERO,
+      StencilOp::Replace => gl::REPLACE,
+      StencilOp::Incr => gl::INCR,
+      StencilOp::IncrWrap => gl::INCR_WRAP,
+      StencilOp::Decr => gl::DECR,
+      StencilOp::DecrWrap => gl::DECR_WRAP,
+      StencilOp::Invert => gl::INVERT,
+   }
+}
@@ -0,0 +1,266 @@
+use crate::renderer::glraw::Ctx;
+use crate::{Caps, GLueError, GLueErrorKind, GlObject, ObjectKind};
+use gl::types::{GLint, GLuint64};
+
+#[derive(Clone, Debug)]
+pub struct ScopeTiming {
+   pub name: String,
+   pub depth: u32,
+   pub nanos: u64,
+}
+
+impl ScopeTiming {
+   pub fn ms(&self) -> f64 {
+      self.nanos as f64 / 1_000_000.0
+   }
+}
+
+#[derive(Clone, Debug)]
+pub struct FrameReport {
+   pub frame: u64,
+   pub scopes: Vec<ScopeTiming>,
+}
+
+impl FrameReport {
+   pub fn total_nanos(&self) -> u64 {
+      self
+         .scopes
+         .iter()
+         .filter(|s| s.depth == 0)
+         .map(|s| s.nanos)
+         .sum()
+   }
+   pub fn total_ms(&self) -> f64 {
+      self.total_nanos() as f64 / 1_000_000.0
+   }
+   pub fn scope(&self, name: &str) -> Option<&ScopeTiming> {
+      self.scopes.iter().find(|s| s.name == name)
+   }
+}
+
+struct PendingScope {
+   name: String,
+   depth: u32,
+   start: usize,
+   end: Option<usize>,
+}
+
+// queries are GlObjects, so a dropped timer frees them through the garbage queue
+struct TimerFrame {
+   frame: u64,
+   queries: Vec<GlObject>,
+   used: usize,
+   scopes: Vec<PendingScope>,
+}
+
+impl TimerFrame {
+   fn empty() -> TimerFrame {
+      TimerFrame {
+         frame: 0,
+         queries: Vec::new(),
+         used: 0,
+         scopes: Vec::new(),
+      }
+   }
+
+   fn stamp(&mut self) -> usize {
+      if self.used == self.queries.len() {
+         self
+            .queries
+            .push(GlObject::new(ObjectKind::Query, create_query()));
+      }
+      let index = self.used;
+      timestamp_query(self.queries[index].id);
+      self.used += 1;
+      index
+   }
+
+   fn is_available(&self) -> bool {
+      match self.used {
+         0 => true,
+         n => query_available(self.queries[n - 1].id),
+      }
+   }
+
+   fn resolve(&self) -> FrameReport {
+      let mut scopes = Vec::new();
+      for scope in self.scopes.iter() {
+         let end = match scope.end {
+            None => continue,
+            Some(end) => end,
+         };
+         let start_ns = query_result(self.queries[scope.start].id);
+         let end_ns = query_result(self.queries[end].id);
+         scopes.push(ScopeTiming {
+            name: scope.name.clone(),
+            depth: scope.depth,
+            nanos: end_ns.saturating_sub(start_ns),
+         });
+      }
+      FrameReport {
+         frame: self.frame,
+         scopes,
+      }
+   }
+
+   fn reset(&mut self, frame: u64) {
+      self.frame = frame;
+      self.used = 0;
+      self.scopes.clear();
+   }
+}
+
+pub struct GpuTimer {
+   ring: Vec<TimerFrame>,
+   frame: u64,
+   stack: Vec<usize>,
+   in_frame: bool,
+   report: Option<FrameReport>,
+}
+
+impl GpuTimer {
+   pub fn new(frames_in_flight: usize) -> Result<GpuTimer, GLueError> {
+      let ctx = Ctx::current();
+      let supported = match ctx.is_es() {
+         true => Caps::query().has_extension("GL_EXT_disjoint_timer_query"),
+         false => ctx.at_least(3, 3),
+      };
+      if !supported || !gl::QueryCounter::is_loaded() || !gl::GetQueryObjectui64v::is_loaded() {
+         return Err(GLueError::from(
+            GLueErrorKind::Unsupported,
+            "timestamp queries are unsupported on this context",
+         ));
+      }
+      let ring = (0..frames_in_flight.max(2))
+         .map(|_| TimerFrame::empty())
+         .collect();
+      Ok(GpuTimer {
+         ring,
+         frame: 0,
+         stack: Vec::new(),
+         in_frame: false,
+         report: None,
+      })
+   }
+
+   fn slot(&mut self) -> &mut TimerFrame {
+      let index = (self.frame % self.ring.len() as u64) as usize;
+      &mut self.ring[index]
+   }
+
+   pub fn begin_frame(&mut self) {
+      if self.in_frame {
+         self.end_frame();
+      }
+      self.frame += 1;
+      let frame = self.frame;
+      let slot = self.slot();
+      let report = match slot.frame {
+         0 => None,
+         _ if slot.is_available() => Some(slot.resolve()),
+         _ => {
+            *slot = TimerFrame::empty();
+            None
+         }
+      };
+      slot.reset(frame);
+      match report {
+         None => {}
+         Some(r) => self.report = Some(r),
+      }
+      self.stack.clear();
+      self.in_frame = true;
+   }
+
+   pub fn end_frame(&mut self) {
+      while !self.stack.is_empty() {
+         self.end_scope();
+      }
+      self.in_frame = false;
+   }
+
+   pub fn begin_scope(&mut self, name: &str) {
+      if !self.in_frame {
+         return;
+      }
+      let depth = self.stack.len() as u32;
+      let slot = self.slot();
+      let start = slot.stamp();
+      slot.scopes.push(PendingScope {
+         name: name.to_string(),
+         depth,
+         start,
+         end: None,
+      });
+      let index = slot.scopes.len() - 1;
+      self.stack.push(index);
+   }
+
+   pub fn end_scope(&mut self) {
+      let index = match self.stack.pop() {
+         None => return,
+         Some(i) => i,
+      };
+      let slot = self.slot();
+      let end = slot.stamp();
+      slot.scopes[index].end = Some(end);
+   }
+
+   pub fn scope<R, F: FnOnce() -> R>(&mut self, name: &str, f: F) -> R {
+      self.begin_scope(name);
+      let result = f();
+      self.end_scope();
+      result
+   }
+
+   pub fn frame(&self) -> u64 {
+      self.frame
+   }
+   pub fn latency(&self) -> usize {
+      self.ring.len()
+   }
+   pub fn report(&self) -> Option<&FrameReport> {
+      self.report.as_ref()
+   }
+
+   pub fn delete(self) {
+      drop(self)
+   }
+}
+
+//QUERIES
+pub(crate) fn create_query() -> u32 {
+   let mut id: u32 = 0;
+   unsafe {
+      gl::GenQueries(1, &mut id);
+   }
+   id
+}
+
+pub(crate) fn timestamp_query(id: u32) {
+   unsafe {
+      gl::QueryCounter(id, gl::TIMESTAMP);
+   }
+}
+
+pub(crate) fn query_available(id: u32) -> bool {
+   let mut available: GLint = 0;
+   unsafe {
+      gl::GetQueryObjectiv(id, gl::QUERY_RESULT_AVAILABLE, &mut available);
+   }
+   available != 0
+}
+
+pub(crate) fn query_result(id: u32) -> u64 {
+   let mut result: GLuint64 = 0;
+   unsafe {
+      gl::GetQueryObjectui64v(id, gl::QUERY_RESULT, &mut result);
+   }
+   result
+}
+
+pub(crate) fn delete_query(id: u32) {
+   unsafe {
+      gl::DeleteQueries(1, &id);
+   }
+}